@@ -53,7 +53,9 @@ export async function activate(context: vscode.ExtensionContext) {
 	// Options to control the language client
 	const clientOptions: LanguageClientOptions = {
 		// Register the server for plain text documents
-		documentSelector: [{ scheme: 'file', language: 'aidl' }],
+		documentSelector: [{ language: 'aidl' }],
+		// Notify the server about changes of the settings
+		synchronize: { configurationSection: 'aidl' },
 	};
 
 	// Create the language client and start the client.
@@ -65,20 +67,86 @@ export async function activate(context: vscode.ExtensionContext) {
 	);
 
 	const statusBar = vscode.window.createStatusBarItem(vscode.StatusBarAlignment.Left);
-	statusBar.text = "rust-analyzer";
-	statusBar.tooltip = "ready";
-	statusBar.command = "rust-analyzer.analyzerStatus";
+	statusBar.text = "$(sync~spin) AIDL";
+	statusBar.tooltip = "Starting AIDL server";
+	statusBar.command = "aidl-lsp.showStatus";
 	statusBar.show();
+	context.subscriptions.push(statusBar);
 
+	context.subscriptions.push(vscode.commands.registerCommand("aidl-lsp.showStatus", async () => {
+		const status = await client.sendRequest<ServerStatus>(statusRequest);
+		const document = await vscode.workspace.openTextDocument({
+			language: "json",
+			content: JSON.stringify(status, null, 2),
+		});
+		await vscode.window.showTextDocument(document);
+	}));
+
+	// Start the client. This will also launch the server
 	client.start();
 	await client.onReady();
-	//client.onNotification(ra.serverStatus, (params) => res.setServerStatus(params));
-	//return res;
 
+	// Poll the status of the server
+	const updateStatusBar = async () => {
+		try {
+			setStatusBar(statusBar, await client.sendRequest<ServerStatus>(statusRequest));
+		} catch (e) {
+			statusBar.text = "$(error) AIDL";
+			statusBar.tooltip = `Could not get the status of the server: ${e}`;
+		}
+	};
+	await updateStatusBar();
+	const timer = setInterval(updateStatusBar, 5000);
+	context.subscriptions.push({ dispose: () => clearInterval(timer) });
+}
+
+const statusRequest = "aidl-lsp/status";
 
-	// Start the client. This will also launch the server
-	//client.start();
+interface LatencyStats {
+	count: number;
+	meanMs: number;
+	p50Ms: number;
+	p90Ms: number;
+	p99Ms: number;
+	maxMs: number;
+}
+
+interface ServerStatus {
+	indexingState: "idle" | "indexing" | "indexed" | "error";
+	indexedFiles: number;
+	virtualDocuments: number;
+	errors: number;
+	memoryEstimate: number;
+	lastIndexDurationMs: number | null;
+	latencies: { [method: string]: LatencyStats };
+}
+
+function setStatusBar(statusBar: vscode.StatusBarItem, status: ServerStatus) {
+	switch (status.indexingState) {
+		case "idle":
+		case "indexing":
+			statusBar.text = "$(sync~spin) AIDL";
+			break;
+		case "error":
+			statusBar.text = "$(error) AIDL";
+			break;
+		case "indexed":
+			statusBar.text = status.errors > 0 ? `$(warning) AIDL (${status.errors})` : "$(check) AIDL";
+			break;
+	}
 
+	// Slowest methods, by 90th percentile
+	const slowest = Object.entries(status.latencies)
+		.sort(([, a], [, b]) => b.p90Ms - a.p90Ms)
+		.slice(0, 5)
+		.map(([method, stats]) => `${method}: p90 ${stats.p90Ms.toFixed(1)} ms (${stats.count}x)`);
+
+	statusBar.tooltip = [
+		`Workspace ${status.indexingState}: ${status.indexedFiles} files, ${status.errors} errors`,
+		status.lastIndexDurationMs !== null ? `Last indexing: ${status.lastIndexDurationMs.toFixed(0)} ms` : "",
+		`Memory: ~${(status.memoryEstimate / 1024 / 1024).toFixed(1)} MiB`,
+		...slowest,
+	].filter(line => line !== "").join("\n");
 }
 
 export function deactivate(): Thenable<void> | undefined {
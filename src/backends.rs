@@ -0,0 +1,435 @@
+use aidl_parser::ast;
+use aidl_parser::symbol::Symbol;
+use serde_derive::Deserialize;
+
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Code generation backends of the AIDL compiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Java,
+    Cpp,
+    Ndk,
+    Rust,
+}
+
+impl Backend {
+    pub fn get_display_name(&self) -> &'static str {
+        match self {
+            Backend::Java => "Java",
+            Backend::Cpp => "C++",
+            Backend::Ndk => "NDK",
+            Backend::Rust => "Rust",
+        }
+    }
+
+    /// Language identifier used for Markdown code blocks
+    pub fn get_language(&self) -> &'static str {
+        match self {
+            Backend::Java => "java",
+            Backend::Cpp | Backend::Ndk => "cpp",
+            Backend::Rust => "rust",
+        }
+    }
+}
+
+// How a type is used, which changes its representation in C++, NDK and Rust
+#[derive(Clone, Copy, PartialEq)]
+enum Usage {
+    Value,
+    In,
+    Out,
+}
+
+/// Signature generated by the given backend for a method, field, constant, item or type
+///
+/// Return `None` for other symbols (e.g. packages).
+pub fn get_signature(
+    global_state: &GlobalState,
+    backend: Backend,
+    symbol: &Symbol,
+) -> Option<String> {
+    let generator = Generator {
+        global_state,
+        backend,
+    };
+
+    Some(match symbol {
+        Symbol::Interface(i, pkg) => generator.interface(i, pkg),
+        Symbol::Parcelable(p, pkg) => generator.parcelable(p, pkg),
+        Symbol::Enum(e, pkg) => generator.enum_(e, pkg),
+        Symbol::Method(m, _) => generator.method(m),
+        Symbol::Const(c, _) => generator.const_(c),
+        Symbol::Field(f, _) => generator.field(f),
+        Symbol::Type(t) => generator.type_(t, Usage::Value, &[]),
+        _ => return None,
+    })
+}
+
+struct Generator<'a> {
+    global_state: &'a GlobalState,
+    backend: Backend,
+}
+
+impl<'a> Generator<'a> {
+    fn interface(&self, i: &ast::Interface, pkg: &ast::Package) -> String {
+        let name = &i.name;
+        match self.backend {
+            Backend::Java => format!("public interface {} extends android.os.IInterface", name),
+            Backend::Cpp => format!(
+                "class {} : public ::android::IInterface  // namespace {}",
+                name,
+                cpp_namespace(&pkg.name, "")
+            ),
+            Backend::Ndk => format!(
+                "class {} : public ::ndk::ICInterface  // namespace {}",
+                name,
+                cpp_namespace(&pkg.name, "::aidl")
+            ),
+            Backend::Rust => format!("pub trait {}: binder::Interface + Send", name),
+        }
+    }
+
+    fn parcelable(&self, p: &ast::Parcelable, pkg: &ast::Package) -> String {
+        let name = &p.name;
+        match self.backend {
+            Backend::Java => format!("public class {} implements android.os.Parcelable", name),
+            Backend::Cpp => format!(
+                "class {} : public ::android::Parcelable  // namespace {}",
+                name,
+                cpp_namespace(&pkg.name, "")
+            ),
+            Backend::Ndk => format!(
+                "class {}  // namespace {}",
+                name,
+                cpp_namespace(&pkg.name, "::aidl")
+            ),
+            Backend::Rust => format!("pub struct {}", name),
+        }
+    }
+
+    fn enum_(&self, e: &ast::Enum, pkg: &ast::Package) -> String {
+        let backing_type = utils::get_enum_backing_type(e);
+        let name = &e.name;
+        match self.backend {
+            Backend::Java => format!("public @interface {}  // {}", name, backing_type),
+            Backend::Cpp => format!(
+                "enum class {} : {}  // namespace {}",
+                name,
+                cpp_primitive(&backing_type),
+                cpp_namespace(&pkg.name, "")
+            ),
+            Backend::Ndk => format!(
+                "enum class {} : {}  // namespace {}",
+                name,
+                cpp_primitive(&backing_type),
+                cpp_namespace(&pkg.name, "::aidl")
+            ),
+            Backend::Rust => format!(
+                "pub struct {}(pub {});",
+                name,
+                rust_primitive(&backing_type)
+            ),
+        }
+    }
+
+    fn method(&self, m: &ast::Method) -> String {
+        let args = m.args.iter().enumerate().map(|(index, arg)| {
+            let usage = match arg.direction {
+                ast::Direction::Out(_) | ast::Direction::InOut(_) => Usage::Out,
+                ast::Direction::In(_) | ast::Direction::Unspecified => Usage::In,
+            };
+            let name = arg.name.clone().unwrap_or_else(|| format!("arg{}", index));
+            let arg_type = self.type_(&arg.arg_type, usage, &arg.annotations);
+
+            match self.backend {
+                Backend::Java | Backend::Cpp | Backend::Ndk => format!("{} {}", arg_type, name),
+                Backend::Rust => format!("{}: {}", name, arg_type),
+            }
+        });
+
+        let has_return_value = m.return_type.kind != ast::TypeKind::Void;
+        let return_type = self.type_(&m.return_type, Usage::Value, &m.annotations);
+
+        match self.backend {
+            Backend::Java => format!(
+                "public {} {}({}) throws android.os.RemoteException;",
+                return_type,
+                m.name,
+                args.collect::<Vec<_>>().join(", ")
+            ),
+            Backend::Cpp | Backend::Ndk => {
+                let status = if self.backend == Backend::Cpp {
+                    "::android::binder::Status"
+                } else {
+                    "::ndk::ScopedAStatus"
+                };
+
+                let mut args: Vec<String> = args.collect();
+                if has_return_value {
+                    let return_type = self.type_(&m.return_type, Usage::Out, &m.annotations);
+                    args.push(format!("{} _aidl_return", return_type));
+                }
+
+                format!("virtual {} {}({}) = 0;", status, m.name, args.join(", "))
+            }
+            Backend::Rust => {
+                let args = std::iter::once(String::from("&self"))
+                    .chain(args)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let return_type = if has_return_value {
+                    return_type
+                } else {
+                    String::from("()")
+                };
+
+                format!(
+                    "fn {}({}) -> binder::Result<{}>;",
+                    m.name, args, return_type
+                )
+            }
+        }
+    }
+
+    fn const_(&self, c: &ast::Const) -> String {
+        let const_type = self.type_(&c.const_type, Usage::Value, &c.annotations);
+        match self.backend {
+            Backend::Java => format!(
+                "public static final {} {} = {};",
+                const_type, c.name, c.value
+            ),
+            Backend::Cpp | Backend::Ndk => {
+                format!("static const {} {} = {};", const_type, c.name, c.value)
+            }
+            Backend::Rust => {
+                let const_type = match c.const_type.kind {
+                    ast::TypeKind::String => "&str".to_owned(),
+                    _ => const_type,
+                };
+                format!("pub const {}: {} = {};", c.name, const_type, c.value)
+            }
+        }
+    }
+
+    fn field(&self, f: &ast::Field) -> String {
+        let field_type = self.type_(&f.field_type, Usage::Value, &f.annotations);
+        let value = f
+            .value
+            .as_ref()
+            .map(|v| format!(" = {}", v))
+            .unwrap_or_default();
+
+        match self.backend {
+            Backend::Java => format!("public {} {}{};", field_type, f.name, value),
+            Backend::Cpp | Backend::Ndk => format!("{} {}{};", field_type, f.name, value),
+            Backend::Rust => format!("pub {}: {},", f.name, field_type),
+        }
+    }
+
+    fn type_(&self, t: &ast::Type, usage: Usage, annotations: &[ast::Annotation]) -> String {
+        let is_nullable = annotations.iter().any(|a| a.name == "@nullable");
+        let is_utf8 = annotations.iter().any(|a| a.name == "@utf8InCpp");
+
+        match self.backend {
+            Backend::Java => self.java_type(t),
+            Backend::Cpp | Backend::Ndk => {
+                let value_type = self.cpp_type(t, is_utf8);
+                let value_type = match t.kind {
+                    _ if !is_nullable => value_type,
+                    ast::TypeKind::IBinder
+                    | ast::TypeKind::Resolved(_, Some(ast::ItemKind::Interface)) => value_type,
+                    ast::TypeKind::Resolved(_, Some(ast::ItemKind::Parcelable))
+                        if self.backend == Backend::Cpp =>
+                    {
+                        format!("::std::unique_ptr<{}>", value_type)
+                    }
+                    _ => format!("::std::optional<{}>", value_type),
+                };
+
+                let is_passed_by_value = matches!(
+                    t.kind,
+                    ast::TypeKind::Primitive
+                        | ast::TypeKind::Void
+                        | ast::TypeKind::Resolved(_, Some(ast::ItemKind::Enum))
+                );
+
+                match usage {
+                    Usage::Value => value_type,
+                    Usage::In if is_passed_by_value => value_type,
+                    Usage::In => format!("const {}&", value_type),
+                    Usage::Out => format!("{}*", value_type),
+                }
+            }
+            Backend::Rust => {
+                let value_type = self.rust_type(t);
+                let value_type = match t.kind {
+                    _ if !is_nullable => value_type,
+                    _ => format!("Option<{}>", value_type),
+                };
+
+                match (usage, &t.kind) {
+                    (Usage::Value, _) => value_type,
+                    (Usage::In, ast::TypeKind::Primitive)
+                    | (Usage::In, ast::TypeKind::Resolved(_, Some(ast::ItemKind::Enum))) => {
+                        value_type
+                    }
+                    (Usage::In, ast::TypeKind::String) if !is_nullable => String::from("&str"),
+                    (Usage::In, ast::TypeKind::Array) | (Usage::In, ast::TypeKind::List)
+                        if !is_nullable =>
+                    {
+                        format!("&[{}]", self.rust_generic_types(t))
+                    }
+                    (Usage::In, _) => format!("&{}", value_type),
+                    (Usage::Out, _) => format!("&mut {}", value_type),
+                }
+            }
+        }
+    }
+
+    fn java_type(&self, t: &ast::Type) -> String {
+        match &t.kind {
+            ast::TypeKind::Array => format!("{}[]", self.java_generic_types(t)),
+            ast::TypeKind::List | ast::TypeKind::Map if !t.generic_types.is_empty() => {
+                format!("java.util.{}<{}>", t.name, self.java_generic_types(t))
+            }
+            ast::TypeKind::List | ast::TypeKind::Map => format!("java.util.{}", t.name),
+            ast::TypeKind::IBinder => String::from("android.os.IBinder"),
+            ast::TypeKind::FileDescriptor => String::from("java.io.FileDescriptor"),
+            ast::TypeKind::ParcelFileDescriptor => String::from("android.os.ParcelFileDescriptor"),
+            ast::TypeKind::ParcelableHolder => String::from("android.os.ParcelableHolder"),
+            ast::TypeKind::Resolved(key, Some(ast::ItemKind::Enum)) => self.enum_backing_type(key),
+            ast::TypeKind::Resolved(key, _) => key.clone(),
+            _ => t.name.clone(),
+        }
+    }
+
+    fn java_generic_types(&self, t: &ast::Type) -> String {
+        t.generic_types
+            .iter()
+            .map(|t| self.java_type(t))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn cpp_type(&self, t: &ast::Type, is_utf8: bool) -> String {
+        let ns_prefix = if self.backend == Backend::Ndk {
+            "::aidl"
+        } else {
+            ""
+        };
+
+        match &t.kind {
+            ast::TypeKind::Primitive => cpp_primitive(&t.name).to_owned(),
+            ast::TypeKind::Void => String::from("void"),
+            ast::TypeKind::String | ast::TypeKind::CharSequence => {
+                if is_utf8 || self.backend == Backend::Ndk {
+                    String::from("::std::string")
+                } else {
+                    String::from("::android::String16")
+                }
+            }
+            ast::TypeKind::Array | ast::TypeKind::List => match t.generic_types.first() {
+                Some(inner) if inner.name == "byte" => String::from("::std::vector<uint8_t>"),
+                Some(inner) => format!("::std::vector<{}>", self.cpp_type(inner, is_utf8)),
+                None => String::from("::std::vector<?>"),
+            },
+            ast::TypeKind::Map => String::from("/* Map is not supported */"),
+            ast::TypeKind::IBinder => match self.backend {
+                Backend::Ndk => String::from("::ndk::SpAIBinder"),
+                _ => String::from("::android::sp<::android::IBinder>"),
+            },
+            ast::TypeKind::FileDescriptor => String::from("::android::base::unique_fd"),
+            ast::TypeKind::ParcelFileDescriptor => match self.backend {
+                Backend::Ndk => String::from("::ndk::ScopedFileDescriptor"),
+                _ => String::from("::android::os::ParcelFileDescriptor"),
+            },
+            ast::TypeKind::ParcelableHolder => match self.backend {
+                Backend::Ndk => String::from("::ndk::AParcelableHolder"),
+                _ => String::from("::android::os::ParcelableHolder"),
+            },
+            ast::TypeKind::Resolved(key, Some(ast::ItemKind::Interface)) => match self.backend {
+                Backend::Ndk => format!("::std::shared_ptr<{}>", cpp_namespace(key, ns_prefix)),
+                _ => format!("::android::sp<{}>", cpp_namespace(key, ns_prefix)),
+            },
+            ast::TypeKind::Resolved(key, _) => cpp_namespace(key, ns_prefix),
+            ast::TypeKind::Unresolved => t.name.clone(),
+        }
+    }
+
+    fn rust_type(&self, t: &ast::Type) -> String {
+        match &t.kind {
+            ast::TypeKind::Primitive => rust_primitive(&t.name).to_owned(),
+            ast::TypeKind::Void => String::from("()"),
+            ast::TypeKind::String | ast::TypeKind::CharSequence => String::from("String"),
+            ast::TypeKind::Array | ast::TypeKind::List => {
+                format!("Vec<{}>", self.rust_generic_types(t))
+            }
+            ast::TypeKind::Map => String::from("/* Map is not supported */"),
+            ast::TypeKind::IBinder => String::from("binder::SpIBinder"),
+            ast::TypeKind::FileDescriptor | ast::TypeKind::ParcelFileDescriptor => {
+                String::from("binder::ParcelFileDescriptor")
+            }
+            ast::TypeKind::ParcelableHolder => String::from("binder::ParcelableHolder"),
+            ast::TypeKind::Resolved(key, Some(ast::ItemKind::Interface)) => {
+                format!("binder::Strong<dyn {}>", get_simple_name(key))
+            }
+            ast::TypeKind::Resolved(key, _) => get_simple_name(key).to_owned(),
+            ast::TypeKind::Unresolved => t.name.clone(),
+        }
+    }
+
+    // Note: the elements of the arrays of bytes are unsigned (e.g. `Vec<u8>`)
+    fn rust_generic_types(&self, t: &ast::Type) -> String {
+        t.generic_types
+            .iter()
+            .map(|t| match t.kind {
+                ast::TypeKind::Primitive if t.name == "byte" => String::from("u8"),
+                _ => self.rust_type(t),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn enum_backing_type(&self, key: ast::ItemKeyRef) -> String {
+        utils::get_item_ast(self.global_state, key)
+            .and_then(|ast| ast.item.as_enum())
+            .map(utils::get_enum_backing_type)
+            .unwrap_or_else(|| String::from("byte"))
+    }
+}
+
+fn get_simple_name(key: &str) -> &str {
+    key.rsplit('.').next().unwrap_or(key)
+}
+
+// e.g. `com.example.Foo` => `::com::example::Foo`
+fn cpp_namespace(qualified_name: &str, prefix: &str) -> String {
+    format!("{}::{}", prefix, qualified_name.replace('.', "::"))
+}
+
+fn cpp_primitive(name: &str) -> &str {
+    match name {
+        "boolean" => "bool",
+        "byte" => "int8_t",
+        "char" => "char16_t",
+        "int" => "int32_t",
+        "long" => "int64_t",
+        other => other,
+    }
+}
+
+fn rust_primitive(name: &str) -> &str {
+    match name {
+        "boolean" => "bool",
+        "byte" => "i8",
+        "char" => "u16",
+        "int" => "i32",
+        "long" => "i64",
+        "float" => "f32",
+        "double" => "f64",
+        other => other,
+    }
+}
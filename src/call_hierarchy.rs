@@ -0,0 +1,127 @@
+//! Call hierarchy between interfaces: interface A "calls" interface B when one of its
+//! methods takes or returns B (e.g. `registerCallback(in IFooCallback cb)`)
+
+use std::collections::BTreeMap;
+
+use aidl_parser::ast;
+use aidl_parser::symbol::Symbol;
+use aidl_parser::traverse::{self, SymbolFilter};
+use lsp_types::{CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall};
+
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Interface declared or referenced (as type or import) at the given position
+pub fn prepare(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    position: &lsp_types::Position,
+) -> Option<CallHierarchyItem> {
+    let key = match traverse::find_symbol_at_line_col(
+        ast,
+        SymbolFilter::All,
+        utils::from_lsp_position(position),
+    )? {
+        Symbol::Type(ast::Type {
+            kind: ast::TypeKind::Resolved(key, Some(ast::ItemKind::Interface)),
+            ..
+        }) => key.clone(),
+        Symbol::Import(i) => i.get_qualified_name(),
+        Symbol::Interface(..) | Symbol::Method(..) => ast.get_key(),
+        _ => return None,
+    };
+
+    create_item(global_state, &key)
+}
+
+/// Interfaces having methods which take or return the given interface
+pub fn get_incoming_calls(
+    global_state: &GlobalState,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyIncomingCall> {
+    let key = match get_item_key(item) {
+        Some(k) => k,
+        None => return Vec::new(),
+    };
+
+    let mut caller_keys: Vec<&ast::ItemKey> = global_state.items_by_key.keys().collect();
+    caller_keys.sort();
+
+    caller_keys
+        .into_iter()
+        .filter_map(|caller_key| {
+            let ast = utils::get_item_ast(global_state, caller_key)?;
+            let from_ranges = get_interface_references(ast).remove(&key)?;
+
+            Some(CallHierarchyIncomingCall {
+                from: create_item(global_state, caller_key)?,
+                from_ranges,
+            })
+        })
+        .collect()
+}
+
+/// Interfaces taken or returned by the methods of the given interface
+pub fn get_outgoing_calls(
+    global_state: &GlobalState,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let ast = match get_item_key(item).and_then(|key| utils::get_item_ast(global_state, &key)) {
+        Some(ast) => ast,
+        None => return Vec::new(),
+    };
+
+    get_interface_references(ast)
+        .into_iter()
+        .filter_map(|(callee_key, from_ranges)| {
+            Some(CallHierarchyOutgoingCall {
+                to: create_item(global_state, &callee_key)?,
+                from_ranges,
+            })
+        })
+        .collect()
+}
+
+// Interfaces referenced by the methods of an interface (except itself), with the
+// ranges of the references
+fn get_interface_references(ast: &ast::Aidl) -> BTreeMap<ast::ItemKey, Vec<lsp_types::Range>> {
+    let mut references: BTreeMap<ast::ItemKey, Vec<lsp_types::Range>> = BTreeMap::new();
+
+    if ast.item.as_interface().is_none() {
+        return references;
+    }
+
+    let own_key = ast.get_key();
+    traverse::walk_types(ast, |t| match &t.kind {
+        ast::TypeKind::Resolved(key, Some(ast::ItemKind::Interface)) if *key != own_key => {
+            references
+                .entry(key.clone())
+                .or_default()
+                .push(utils::to_lsp_range(&t.symbol_range));
+        }
+        _ => (),
+    });
+
+    references
+}
+
+fn get_item_key(item: &CallHierarchyItem) -> Option<ast::ItemKey> {
+    item.data.as_ref()?.as_str().map(str::to_owned)
+}
+
+fn create_item(global_state: &GlobalState, key: ast::ItemKeyRef) -> Option<CallHierarchyItem> {
+    let uri = global_state.items_by_key.get(key)?;
+    let ast = utils::get_item_ast(global_state, key)?;
+    let interface = ast.item.as_interface()?;
+
+    Some(CallHierarchyItem {
+        name: interface.name.clone(),
+        kind: lsp_types::SymbolKind::INTERFACE,
+        tags: None,
+        detail: Some(ast.package.name.clone()),
+        uri: uri.clone(),
+        range: utils::to_lsp_range(&interface.full_range),
+        selection_range: utils::to_lsp_range(&interface.symbol_range),
+        data: Some(serde_json::Value::String(key.to_owned())),
+    })
+}
@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use aidl_parser::ast;
+
+use crate::document_link;
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Run the checks which involve several files of the workspace and return the
+/// resulting diagnostics, by file
+pub fn check_workspace(
+    global_state: &GlobalState,
+    uris_by_key: &HashMap<ast::ItemKey, Vec<lsp_types::Url>>,
+) -> HashMap<lsp_types::Url, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics = HashMap::new();
+    let config = &global_state.config;
+
+    if let Some(severity) = config.lints.duplicated_items.to_lsp() {
+        check_duplicated_items(global_state, uris_by_key, severity, &mut diagnostics);
+    }
+
+    if let Some(severity) = config.lints.file_locations.to_lsp() {
+        check_file_locations(global_state, severity, &mut diagnostics);
+    }
+
+    if let Some(severity) = config.lints.doc_links.to_lsp() {
+        check_doc_links(global_state, severity, &mut diagnostics);
+    }
+
+    if let Some(severity) = config.lints.unused_items.to_lsp() {
+        check_unused_items(global_state, severity, &mut diagnostics);
+    }
+
+    diagnostics
+}
+
+/// Directories containing the package hierarchies
+#[derive(Debug, Default)]
+pub struct ImportRoots {
+    /// Configured roots (deepest first), which take priority over the inferred ones
+    configured: Vec<PathBuf>,
+    /// Root inferred for each directory containing AIDL files: the one most of its files
+    /// agree on
+    by_dir: HashMap<PathBuf, PathBuf>,
+    /// All the inferred roots, the most common first
+    inferred: Vec<PathBuf>,
+}
+
+impl ImportRoots {
+    /// The import root of the files of the given directory
+    pub fn get(&self, dir: &Path) -> Option<&Path> {
+        self.configured
+            .iter()
+            .find(|root| dir.starts_with(root))
+            .or_else(|| self.by_dir.get(dir))
+            .or_else(|| self.inferred.iter().find(|root| dir.starts_with(root)))
+            .map(PathBuf::as_path)
+    }
+}
+
+/// Collect the import roots: the configured ones and the ones inferred from the files
+/// whose directory matches their package
+///
+/// A file whose package matches only the end of its directory (e.g. `package foo;` in
+/// `src/com/foo`) does not change the root of the other files of the directory.
+pub fn get_import_roots(global_state: &GlobalState) -> ImportRoots {
+    let mut configured: Vec<PathBuf> = global_state
+        .config
+        .import_roots
+        .iter()
+        .map(|root| match &global_state.root_path {
+            Some(root_path) if root.is_relative() => root_path.join(root),
+            _ => root.clone(),
+        })
+        .collect();
+    configured.sort_by(|a, b| {
+        b.components()
+            .count()
+            .cmp(&a.components().count())
+            .then(a.cmp(b))
+    });
+    configured.dedup();
+
+    // Number of files inferring each root, in total and by directory
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    let mut counts_by_dir: HashMap<PathBuf, HashMap<PathBuf, usize>> = HashMap::new();
+    for (uri, fr) in &global_state.file_results {
+        if let Some((dir, root)) = infer_import_root(uri, fr) {
+            *counts.entry(root.clone()).or_default() += 1;
+            *counts_by_dir
+                .entry(dir)
+                .or_default()
+                .entry(root)
+                .or_default() += 1;
+        }
+    }
+
+    // Most common first, then deepest first
+    let cmp_roots = |a: &PathBuf, b: &PathBuf| {
+        counts[b]
+            .cmp(&counts[a])
+            .then(b.components().count().cmp(&a.components().count()))
+            .then(a.cmp(b))
+    };
+
+    let by_dir = counts_by_dir
+        .into_iter()
+        .filter_map(|(dir, dir_counts)| {
+            let root = dir_counts
+                .into_iter()
+                .min_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(cmp_roots(a, b)))?
+                .0;
+            Some((dir, root))
+        })
+        .collect();
+
+    let mut inferred: Vec<PathBuf> = counts.keys().cloned().collect();
+    inferred.sort_by(cmp_roots);
+
+    ImportRoots {
+        configured,
+        by_dir,
+        inferred,
+    }
+}
+
+// The directory of the file and the root inferred from its package (`None` if the
+// package does not match the directory)
+fn infer_import_root(
+    uri: &lsp_types::Url,
+    fr: &aidl_parser::ParseFileResult<lsp_types::Url>,
+) -> Option<(PathBuf, PathBuf)> {
+    let ast = fr.ast.as_ref()?;
+    let path = uri.to_file_path().ok()?;
+    let dir = path.parent()?;
+    let mut root = dir;
+    for segment in ast.package.name.rsplit('.') {
+        if root.file_name()?.to_str()? != segment {
+            return None;
+        }
+        root = root.parent()?;
+    }
+
+    Some((dir.to_path_buf(), root.to_path_buf()))
+}
+
+/// The package expected for the given file, according to its import root
+/// (`None` if the file is not located under any import root)
+pub fn get_expected_package(global_state: &GlobalState, path: &Path) -> Option<String> {
+    let dir = path.parent()?;
+    let root = global_state.import_roots.get(dir)?;
+
+    let segments: Vec<&str> = dir
+        .strip_prefix(root)
+        .ok()?
+        .iter()
+        .map(|s| s.to_str())
+        .collect::<Option<_>>()?;
+
+    Some(segments.join("."))
+}
+
+/// The location expected for the given file, according to its package and item name
+pub fn get_expected_path(
+    global_state: &GlobalState,
+    path: &Path,
+    ast: &ast::Aidl,
+) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let root = global_state.import_roots.get(dir)?;
+
+    let mut expected_path = root.to_path_buf();
+    expected_path.extend(ast.package.name.split('.'));
+    expected_path.push(format!("{}.aidl", ast.item.get_name()));
+
+    Some(expected_path)
+}
+
+// Package not matching the directory (relative to the import root) and file name
+// not matching the item name
+fn check_file_locations(
+    global_state: &GlobalState,
+    severity: lsp_types::DiagnosticSeverity,
+    diagnostics: &mut HashMap<lsp_types::Url, Vec<lsp_types::Diagnostic>>,
+) {
+    for (uri, ast) in global_state
+        .file_results
+        .iter()
+        .filter_map(|(uri, fr)| fr.ast.as_ref().map(|ast| (uri, ast)))
+    {
+        let path = match uri.to_file_path() {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+
+        match get_expected_package(global_state, &path) {
+            Some(expected_package) if expected_package != ast.package.name => {
+                diagnostics
+                    .entry(uri.clone())
+                    .or_default()
+                    .push(lsp_types::Diagnostic {
+                        range: utils::to_lsp_range(&ast.package.symbol_range),
+                        severity: Some(severity),
+                        code: Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+                        message: format!(
+                            "Package `{}` does not match the location of the file (expected `{}`)",
+                            ast.package.name, expected_package
+                        ),
+                        ..lsp_types::Diagnostic::default()
+                    });
+            }
+            _ => (),
+        }
+
+        let item_name = ast.item.get_name();
+        if path.file_stem().and_then(|s| s.to_str()) != Some(item_name) {
+            diagnostics
+                .entry(uri.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: utils::to_lsp_range(ast.item.get_symbol_range()),
+                    severity: Some(severity),
+                    code: Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+                    message: format!(
+                        "Name of `{}` does not match the file name (expected `{}.aidl`)",
+                        item_name, item_name
+                    ),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+}
+
+// References in doc comments (`{@link Foo}`, `@see Foo`) which cannot be resolved
+fn check_doc_links(
+    global_state: &GlobalState,
+    severity: lsp_types::DiagnosticSeverity,
+    diagnostics: &mut HashMap<lsp_types::Url, Vec<lsp_types::Diagnostic>>,
+) {
+    for (uri, ast) in global_state
+        .file_results
+        .iter()
+        .filter_map(|(uri, fr)| fr.ast.as_ref().map(|ast| (uri, ast)))
+    {
+        let content = match global_state.file_contents.get(uri) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let references = document_link::get_doc_references(content, global_state.position_encoding);
+        for (range, reference) in references {
+            if document_link::resolve_doc_reference(global_state, ast, reference).is_some() {
+                continue;
+            }
+
+            diagnostics
+                .entry(uri.clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+                    message: format!("Unresolved link `{}`", reference),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+}
+
+// Items with the same qualified name defined in several files
+fn check_duplicated_items(
+    global_state: &GlobalState,
+    uris_by_key: &HashMap<ast::ItemKey, Vec<lsp_types::Url>>,
+    severity: lsp_types::DiagnosticSeverity,
+    diagnostics: &mut HashMap<lsp_types::Url, Vec<lsp_types::Diagnostic>>,
+) {
+    for (key, uris) in uris_by_key.iter().filter(|(_, uris)| uris.len() > 1) {
+        let locations: Vec<(&lsp_types::Url, lsp_types::Location)> = uris
+            .iter()
+            .filter_map(|uri| {
+                let ast = global_state.file_results.get(uri)?.ast.as_ref()?;
+                Some((
+                    uri,
+                    lsp_types::Location {
+                        uri: uri.clone(),
+                        range: utils::to_lsp_range(ast.item.get_symbol_range()),
+                    },
+                ))
+            })
+            .collect();
+
+        for (uri, location) in &locations {
+            let related_infos = locations
+                .iter()
+                .filter(|(other_uri, _)| other_uri != uri)
+                .map(
+                    |(_, other_location)| lsp_types::DiagnosticRelatedInformation {
+                        location: other_location.clone(),
+                        message: String::from("other definition"),
+                    },
+                )
+                .collect();
+
+            diagnostics
+                .entry((*uri).clone())
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: location.range,
+                    severity: Some(severity),
+                    code: Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+                    message: format!("Duplicated definition of `{}`", key),
+                    related_information: Some(related_infos),
+                    ..lsp_types::Diagnostic::default()
+                });
+        }
+    }
+}
+
+// Items which are never referenced (as resolved type) nor imported by any file
+fn check_unused_items(
+    global_state: &GlobalState,
+    severity: lsp_types::DiagnosticSeverity,
+    diagnostics: &mut HashMap<lsp_types::Url, Vec<lsp_types::Diagnostic>>,
+) {
+    let used: HashSet<ast::ItemKey> = global_state
+        .file_results
+        .values()
+        .filter_map(|fr| fr.ast.as_ref())
+        .flat_map(|ast| {
+            utils::get_resolved_type_keys(ast)
+                .into_iter()
+                .chain(ast.imports.iter().map(|i| i.get_qualified_name()))
+        })
+        .collect();
+
+    for (uri, ast) in global_state
+        .file_results
+        .iter()
+        .filter_map(|(uri, fr)| fr.ast.as_ref().map(|ast| (uri, ast)))
+    {
+        let key = ast.get_key();
+        if used.contains(&key) {
+            continue;
+        }
+
+        diagnostics
+            .entry(uri.clone())
+            .or_default()
+            .push(lsp_types::Diagnostic {
+                range: utils::to_lsp_range(ast.item.get_symbol_range()),
+                severity: Some(severity),
+                code: Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+                message: format!("Unused item `{}`", key),
+                tags: Some(Vec::from([lsp_types::DiagnosticTag::UNNECESSARY])),
+                ..lsp_types::Diagnostic::default()
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing;
+
+    fn interface(package: &str, name: &str) -> String {
+        format!("package {};\ninterface {} {{}}\n", package, name)
+    }
+
+    fn index(files: &[(&str, String)]) -> GlobalState {
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (*p, c.as_str())).collect();
+        indexing::index_test_files(&files).0
+    }
+
+    fn expected_package(global_state: &GlobalState, path: &str) -> Option<String> {
+        get_expected_package(global_state, &Path::new("/ws").join(path))
+    }
+
+    fn root(global_state: &GlobalState, dir: &str) -> Option<PathBuf> {
+        global_state
+            .import_roots
+            .get(&Path::new("/ws").join(dir))
+            .map(Path::to_path_buf)
+    }
+
+    #[test]
+    fn inferred_roots() {
+        let global_state = index(&[
+            ("src/com/a/IA.aidl", interface("com.a", "IA")),
+            ("other/b/IB.aidl", interface("b", "IB")),
+        ]);
+
+        assert_eq!(root(&global_state, "src/com/a"), Some("/ws/src".into()));
+        assert_eq!(root(&global_state, "other/b"), Some("/ws/other".into()));
+        assert_eq!(
+            expected_package(&global_state, "src/com/a/IA.aidl").as_deref(),
+            Some("com.a")
+        );
+
+        // Directories without matching files use the enclosing roots
+        assert_eq!(
+            expected_package(&global_state, "src/com/c/IC.aidl").as_deref(),
+            Some("com.c")
+        );
+        assert_eq!(expected_package(&global_state, "third/ID.aidl"), None);
+    }
+
+    #[test]
+    fn majority_of_directory() {
+        // `package foo;` would make `src/com` a root, but most files of the directory
+        // agree on `src`
+        let global_state = index(&[
+            ("src/com/foo/IA.aidl", interface("com.foo", "IA")),
+            ("src/com/foo/IB.aidl", interface("com.foo", "IB")),
+            ("src/com/foo/IC.aidl", interface("foo", "IC")),
+        ]);
+
+        assert_eq!(root(&global_state, "src/com/foo"), Some("/ws/src".into()));
+        assert_eq!(
+            expected_package(&global_state, "src/com/foo/IC.aidl").as_deref(),
+            Some("com.foo")
+        );
+
+        let ic_diagnostics = &global_state.workspace_diagnostics
+            [&utils::path_to_uri(Path::new("/ws/src/com/foo/IC.aidl")).unwrap()];
+        assert_eq!(ic_diagnostics.len(), 1);
+        assert_eq!(
+            ic_diagnostics[0].message,
+            "Package `foo` does not match the location of the file (expected `com.foo`)"
+        );
+        assert!(!global_state
+            .workspace_diagnostics
+            .contains_key(&utils::path_to_uri(Path::new("/ws/src/com/foo/IA.aidl")).unwrap()));
+    }
+
+    #[test]
+    fn tie_in_directory() {
+        // Tie in `src/com/foo`: the root inferred by most files of the workspace wins
+        let global_state = index(&[
+            ("src/com/foo/IA.aidl", interface("com.foo", "IA")),
+            ("src/com/foo/IB.aidl", interface("foo", "IB")),
+            ("src/com/bar/IC.aidl", interface("com.bar", "IC")),
+        ]);
+        assert_eq!(root(&global_state, "src/com/foo"), Some("/ws/src".into()));
+
+        let global_state = index(&[
+            ("src/com/foo/IA.aidl", interface("com.foo", "IA")),
+            ("src/com/foo/IB.aidl", interface("foo", "IB")),
+            ("src/com/bar/IC.aidl", interface("bar", "IC")),
+        ]);
+        assert_eq!(
+            root(&global_state, "src/com/foo"),
+            Some("/ws/src/com".into())
+        );
+
+        // Same count: the deepest root wins
+        let global_state = index(&[
+            ("src/com/foo/IA.aidl", interface("com.foo", "IA")),
+            ("src/com/foo/IB.aidl", interface("foo", "IB")),
+        ]);
+        assert_eq!(
+            root(&global_state, "src/com/foo"),
+            Some("/ws/src/com".into())
+        );
+    }
+
+    #[test]
+    fn configured_roots() {
+        let files = [
+            ("src/com/foo/IA.aidl", interface("com.foo", "IA")),
+            ("src/com/foo/IB.aidl", interface("com.foo", "IB")),
+        ];
+
+        let mut global_state = index(&files);
+        global_state.config.import_roots = Vec::from(["src/com".into()]);
+        global_state.import_roots = get_import_roots(&global_state);
+
+        assert_eq!(
+            root(&global_state, "src/com/foo"),
+            Some("/ws/src/com".into())
+        );
+        assert_eq!(
+            expected_package(&global_state, "src/com/foo/IA.aidl").as_deref(),
+            Some("foo")
+        );
+
+        // Deepest configured root first, then the inferred ones
+        global_state.config.import_roots = Vec::from(["/ws".into(), "src/com/foo".into()]);
+        global_state.import_roots = get_import_roots(&global_state);
+        assert_eq!(
+            root(&global_state, "src/com/foo/bar"),
+            Some("/ws/src/com/foo".into())
+        );
+        assert_eq!(root(&global_state, "src/com"), Some("/ws".into()));
+    }
+}
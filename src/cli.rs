@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use tracing::metadata::LevelFilter;
+
+use crate::log::{LogOptions, LOG_LEVEL_ENV};
+use crate::transport::Transport;
+
+const HELP: &str = "\
+AIDL Language Server Protocol (LSP) server
+
+USAGE:
+    aidl-lsp [OPTIONS]
+
+OPTIONS:
+    --stdio                 Communicate over stdin/stdout (default)
+    --listen <ADDRESS>      Wait for a client on the given address
+    --connect <ADDRESS>     Connect to a client listening on the given address
+    --log-level <LEVEL>     Maximum level of the logs: off, error, warn, info (default),
+                            debug or trace (overrides $AIDL_LSP_LOG)
+    --log-file <PATH>       Write the logs to the given file too (rolled over at 10 MiB)
+    --log-format <FORMAT>   Format of the logs: text (default) or json
+    -h, --help              Print help information
+    -V, --version           Print version information
+
+ADDRESS:
+    <host>:<port>           TCP socket (e.g. 127.0.0.1:9257)
+    unix:<path>             Unix domain socket (e.g. unix:/tmp/aidl-lsp.sock)
+";
+
+/// Command-line arguments
+#[derive(Debug)]
+pub struct Args {
+    pub transport: Transport,
+    pub log: LogOptions,
+}
+
+/// Parse the command-line arguments (`None` if the program should exit, e.g. after
+/// printing the help)
+pub fn parse_args() -> Result<Option<Args>> {
+    let mut args = pico_args::Arguments::from_env();
+
+    if args.contains(["-h", "--help"]) {
+        print!("{}", HELP);
+        return Ok(None);
+    }
+
+    if args.contains(["-V", "--version"]) {
+        println!("aidl-lsp {}", env!("CARGO_PKG_VERSION"));
+        return Ok(None);
+    }
+
+    let stdio = args.contains("--stdio");
+    let listen: Option<String> = args.opt_value_from_str("--listen")?;
+    let connect: Option<String> = args.opt_value_from_str("--connect")?;
+    let log_level: Option<LevelFilter> = args.opt_value_from_str("--log-level")?;
+    let log_file: Option<std::path::PathBuf> = args.opt_value_from_str("--log-file")?;
+    let log_format: Option<String> = args.opt_value_from_str("--log-format")?;
+
+    let remaining = args.finish();
+    if !remaining.is_empty() {
+        anyhow::bail!("Unexpected arguments: {:?} (see --help)", remaining);
+    }
+
+    let transport = match (stdio, listen, connect) {
+        (_, None, None) => Transport::Stdio,
+        (false, Some(address), None) => Transport::Listen(address.parse()?),
+        (false, None, Some(address)) => Transport::Connect(address.parse()?),
+        _ => anyhow::bail!("--stdio, --listen and --connect are mutually exclusive"),
+    };
+
+    let log_level = match (log_level, std::env::var(LOG_LEVEL_ENV)) {
+        (Some(level), _) => Some(level),
+        (None, Ok(level)) => Some(
+            level
+                .parse()
+                .ok()
+                .with_context(|| format!("Invalid {}: `{}`", LOG_LEVEL_ENV, level))?,
+        ),
+        (None, Err(_)) => None,
+    };
+
+    let json = match log_format.as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => anyhow::bail!("Invalid log format `{}` (see --help)", format),
+    };
+
+    let log = LogOptions {
+        level: log_level,
+        file: log_file,
+        json,
+    };
+
+    Ok(Some(Args { transport, log }))
+}
@@ -0,0 +1,702 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use aidl_parser::{ast, traverse};
+use lsp_types::{CodeAction, CodeActionKind, CodeActionOrCommand, TextEdit, WorkspaceEdit};
+
+use crate::checks;
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Collect the quick fixes applicable to the given range of a file.
+///
+/// The fixes are computed from the AST (and not from the diagnostics sent by the
+/// client), the client diagnostics are only attached to the resulting code actions.
+pub fn get_quick_fixes(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    diagnostics: &[lsp_types::Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+
+    add_missing_imports(global_state, uri, ast, range, &mut actions);
+    remove_unused_imports(global_state, uri, ast, range, &mut actions);
+
+    // The location of virtual documents cannot be fixed
+    if let Ok(path) = uri.to_file_path() {
+        fix_package(global_state, uri, &path, ast, range, &mut actions);
+        rename_file(uri, &path, ast, range, &mut actions);
+    }
+    add_missing_directions(uri, ast, range, &mut actions);
+    fix_oneway_return_types(uri, ast, range, &mut actions);
+
+    // Attach the diagnostics of the client which are concerned by each fix
+    actions
+        .into_iter()
+        .map(|(target_range, mut action)| {
+            let related_diagnostics: Vec<_> = diagnostics
+                .iter()
+                .filter(|d| utils::ranges_overlap(&d.range, &target_range))
+                .cloned()
+                .collect();
+
+            if !related_diagnostics.is_empty() {
+                action.diagnostics = Some(related_diagnostics);
+            }
+
+            CodeActionOrCommand::CodeAction(action)
+        })
+        .collect()
+}
+
+/// Name of the command which organizes the imports of the file given as argument
+pub const ORGANIZE_IMPORTS_COMMAND: &str = "aidl-lsp.organizeImports";
+
+/// Code action which organizes the imports of a file (see [`get_organize_imports_edit`])
+pub fn get_organize_imports_action(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+) -> Option<CodeActionOrCommand> {
+    let edit = get_organize_imports_edit(global_state, uri, ast)?;
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: String::from("Organize imports"),
+        kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+        edit: Some(edit),
+        ..CodeAction::default()
+    }))
+}
+
+/// Rewrite the import block of a file:
+/// - keep the imports of the resolved types and remove the unused ones
+/// - add the imports of unresolved types which match exactly one item of the workspace
+/// - remove duplicates, sort alphabetically and group by top-level package
+///
+/// Return `None` if the imports are already organized, or if the import block contains
+/// something else (e.g. comments) which would be lost.
+pub fn get_organize_imports_edit(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+) -> Option<WorkspaceEdit> {
+    let declared_parcelables: HashSet<String> = ast
+        .declared_parcelables
+        .iter()
+        .map(|dp| dp.get_qualified_name())
+        .collect();
+
+    let mut needed: BTreeSet<String> = utils::get_resolved_type_keys(ast)
+        .into_iter()
+        .filter(|key| !declared_parcelables.contains(key))
+        .collect();
+
+    traverse::walk_types(ast, |t| {
+        if t.kind != ast::TypeKind::Unresolved || t.name.contains('.') {
+            return;
+        }
+
+        if let Some(key) = utils::find_unique_item_key(global_state, &t.name) {
+            needed.insert(key.clone());
+        }
+    });
+
+    // Group by top-level package, separated by an empty line unless disabled in the
+    // formatter options (BTreeMap and BTreeSet keep everything sorted)
+    let mut groups: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for key in &needed {
+        let top_level = key.split('.').next().unwrap_or_default();
+        groups.entry(top_level).or_default().push(key);
+    }
+
+    let new_block = groups
+        .values()
+        .map(|keys| {
+            keys.iter()
+                .map(|key| format!("import {};\n", key))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(if global_state.config.formatter.group_imports {
+            "\n"
+        } else {
+            ""
+        });
+
+    let (range, new_text) = match (ast.imports.first(), ast.imports.last()) {
+        (Some(first), Some(last)) => {
+            let range = lsp_types::Range {
+                start: utils::to_lsp_line_range(&first.full_range).start,
+                end: utils::to_lsp_line_range(&last.full_range).end,
+            };
+
+            let content = global_state.file_contents.get(uri)?;
+            if !contains_only_imports(content, &ast.imports) {
+                return None;
+            }
+
+            if utils::get_lines(content, &range).as_deref() == Some(new_block.as_str()) {
+                return None;
+            }
+
+            (range, new_block)
+        }
+        _ => {
+            if new_block.is_empty() {
+                return None;
+            }
+
+            let pos = utils::to_lsp_line_range(&ast.package.full_range).end;
+            (
+                lsp_types::Range {
+                    start: pos,
+                    end: pos,
+                },
+                format!("\n{}", new_block),
+            )
+        }
+    };
+
+    Some(single_file_edit(
+        uri,
+        Vec::from([TextEdit { range, new_text }]),
+    ))
+}
+
+// Whether the lines of the imports contain nothing but the imports and blank lines
+// (the range of an import does not include its semicolon)
+fn contains_only_imports(content: &str, imports: &[ast::Import]) -> bool {
+    let is_blank = |s: &str| s.chars().all(|c| c.is_whitespace() || c == ';');
+
+    let (first, last) = match (imports.first(), imports.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return true,
+    };
+
+    let block_start = content[..first.full_range.start.offset]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let block_end = content[last.full_range.end.offset..]
+        .find('\n')
+        .map_or(content.len(), |i| last.full_range.end.offset + i);
+
+    let mut pos = block_start;
+    for import in imports {
+        match content.get(pos..import.full_range.start.offset) {
+            Some(between) if is_blank(between) => pos = import.full_range.end.offset,
+            _ => return false,
+        }
+    }
+
+    is_blank(&content[pos..block_end])
+}
+
+/// Import an unresolved type when exactly one item of the workspace has this name
+fn add_missing_imports(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    let mut already_fixed = HashSet::new();
+
+    traverse::walk_types(ast, |t| {
+        if t.kind != ast::TypeKind::Unresolved || t.name.contains('.') {
+            return;
+        }
+
+        let type_range = utils::to_lsp_range(&t.symbol_range);
+        if !utils::ranges_overlap(&type_range, range) || !already_fixed.insert(t.name.clone()) {
+            return;
+        }
+
+        if let Some(key) = utils::find_unique_item_key(global_state, &t.name) {
+            actions.push((
+                type_range,
+                CodeAction {
+                    title: format!("Import `{}`", key),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(single_file_edit(
+                        uri,
+                        Vec::from([utils::get_add_import_edit(ast, key)]),
+                    )),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                },
+            ));
+        }
+    });
+}
+
+/// Remove the imports which are resolved but not referenced by any type
+fn remove_unused_imports(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    let used = utils::get_resolved_type_keys(ast);
+
+    for import in &ast.imports {
+        let qualified_name = import.get_qualified_name();
+        if used.contains(&qualified_name)
+            || !global_state.items_by_key.contains_key(&qualified_name)
+        {
+            continue;
+        }
+
+        let import_range = utils::to_lsp_range(&import.symbol_range);
+        if !utils::ranges_overlap(&import_range, range) {
+            continue;
+        }
+
+        actions.push((
+            import_range,
+            CodeAction {
+                title: format!("Remove unused import `{}`", qualified_name),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(single_file_edit(
+                    uri,
+                    Vec::from([TextEdit {
+                        range: utils::to_lsp_line_range(&import.full_range),
+                        new_text: String::new(),
+                    }]),
+                )),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            },
+        ));
+    }
+}
+
+/// Rewrite the package declaration according to the location of the file, or move
+/// the file according to its package
+fn fix_package(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    path: &Path,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    let package_range = utils::to_lsp_range(&ast.package.full_range);
+    if !utils::ranges_overlap(&package_range, range) {
+        return;
+    }
+
+    let expected_package = match checks::get_expected_package(global_state, path) {
+        Some(p) if p != ast.package.name => p,
+        _ => return,
+    };
+
+    actions.push((
+        package_range,
+        CodeAction {
+            title: format!("Change package to `{}`", expected_package),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(single_file_edit(
+                uri,
+                Vec::from([TextEdit {
+                    range: utils::to_lsp_range(&ast.package.symbol_range),
+                    new_text: expected_package,
+                }]),
+            )),
+            ..CodeAction::default()
+        },
+    ));
+
+    let new_uri = checks::get_expected_path(global_state, path, ast)
+        .and_then(|p| utils::path_to_uri(&p).ok().map(|uri| (p, uri)));
+
+    if let Some((new_path, new_uri)) = new_uri {
+        let display_path = global_state
+            .root_path
+            .as_ref()
+            .and_then(|root| new_path.strip_prefix(root).ok())
+            .unwrap_or(&new_path);
+
+        actions.push((
+            package_range,
+            CodeAction {
+                title: format!("Move file to `{}`", display_path.to_string_lossy()),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(utils::get_rename_file_edit(uri, &new_uri)),
+                ..CodeAction::default()
+            },
+        ));
+    }
+}
+
+/// Rename the file according to the name of the declared item
+fn rename_file(
+    uri: &lsp_types::Url,
+    path: &Path,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    let item_range = utils::to_lsp_range(ast.item.get_symbol_range());
+    if !utils::ranges_overlap(&item_range, range) {
+        return;
+    }
+
+    let item_name = ast.item.get_name();
+    if path.file_stem().and_then(|s| s.to_str()) == Some(item_name) {
+        return;
+    }
+
+    let new_uri = match utils::path_to_uri(&path.with_file_name(format!("{}.aidl", item_name))) {
+        Ok(u) => u,
+        Err(_) => return,
+    };
+
+    actions.push((
+        item_range,
+        CodeAction {
+            title: format!("Rename file to `{}.aidl`", item_name),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(utils::get_rename_file_edit(uri, &new_uri)),
+            ..CodeAction::default()
+        },
+    ));
+}
+
+/// Add the `in` direction to the arguments which require a direction
+fn add_missing_directions(
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    traverse::walk_args(ast, |_, arg| {
+        if arg.direction != ast::Direction::Unspecified || !requires_direction(&arg.arg_type) {
+            return;
+        }
+
+        let arg_range = utils::to_lsp_range(&arg.full_range);
+        if !utils::ranges_overlap(&arg_range, range) {
+            return;
+        }
+
+        let insert_pos = utils::to_lsp_range(&arg.arg_type.full_range).start;
+        actions.push((
+            arg_range,
+            CodeAction {
+                title: String::from("Add `in` direction"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(single_file_edit(
+                    uri,
+                    Vec::from([TextEdit {
+                        range: lsp_types::Range {
+                            start: insert_pos,
+                            end: insert_pos,
+                        },
+                        new_text: String::from("in "),
+                    }]),
+                )),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            },
+        ));
+    });
+}
+
+/// Replace the return type of oneway methods with `void`
+fn fix_oneway_return_types(
+    uri: &lsp_types::Url,
+    ast: &ast::Aidl,
+    range: &lsp_types::Range,
+    actions: &mut Vec<(lsp_types::Range, CodeAction)>,
+) {
+    traverse::walk_methods(ast, |m| {
+        if !m.oneway || m.return_type.kind == ast::TypeKind::Void {
+            return;
+        }
+
+        let return_type_range = utils::to_lsp_range(&m.return_type.full_range);
+        if !utils::ranges_overlap(&return_type_range, range) {
+            return;
+        }
+
+        actions.push((
+            return_type_range,
+            CodeAction {
+                title: String::from("Change return type to `void`"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(single_file_edit(
+                    uri,
+                    Vec::from([TextEdit {
+                        range: return_type_range,
+                        new_text: String::from("void"),
+                    }]),
+                )),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            },
+        ));
+    });
+}
+
+// Same requirement as in aidl_parser (arrays, lists, maps and parcelables)
+fn requires_direction(t: &ast::Type) -> bool {
+    matches!(
+        t.kind,
+        ast::TypeKind::Array
+            | ast::TypeKind::List
+            | ast::TypeKind::Map
+            | ast::TypeKind::Resolved(_, Some(ast::ItemKind::Parcelable))
+    )
+}
+
+fn single_file_edit(uri: &lsp_types::Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..WorkspaceEdit::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing;
+
+    const IB: (&str, &str) = ("b/IB.aidl", "package b;\ninterface IB {}\n");
+    const IC: (&str, &str) = ("c/IC.aidl", "package c;\ninterface IC {}\n");
+    const ID: (&str, &str) = ("c/d/ID.aidl", "package c.d;\ninterface ID {}\n");
+
+    fn uri(path: &str) -> lsp_types::Url {
+        utils::path_to_uri(&Path::new("/ws").join(path)).unwrap()
+    }
+
+    // Text edits of a workspace edit of the given file
+    fn get_edits(edit: &WorkspaceEdit, path: &str) -> Vec<TextEdit> {
+        edit.changes.as_ref().unwrap()[&uri(path)].clone()
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> lsp_types::Range {
+        lsp_types::Range {
+            start: lsp_types::Position::new(start.0, start.1),
+            end: lsp_types::Position::new(end.0, end.1),
+        }
+    }
+
+    fn organize_imports(content: &str) -> Option<Vec<TextEdit>> {
+        let (global_state, _receiver) =
+            indexing::index_test_files(&[("a/IA.aidl", content), IB, IC, ID]);
+        let ast = global_state.file_results[&uri("a/IA.aidl")]
+            .ast
+            .as_ref()
+            .unwrap();
+
+        get_organize_imports_edit(&global_state, &uri("a/IA.aidl"), ast)
+            .map(|edit| get_edits(&edit, "a/IA.aidl"))
+    }
+
+    #[test]
+    fn organize_imports_sorts_and_groups() {
+        let content = "package a;\n\nimport c.IC;\nimport b.IB;\nimport c.IC;\n\ninterface IA {\n    void f(IB b, IC c, ID d);\n}\n";
+        let edits = organize_imports(content).unwrap();
+
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range, range((2, 0), (5, 0)));
+        assert_eq!(
+            edits[0].new_text,
+            "import b.IB;\n\nimport c.IC;\nimport c.d.ID;\n"
+        );
+    }
+
+    #[test]
+    fn organize_imports_removes_unused() {
+        let content =
+            "package a;\n\nimport b.IB;\nimport c.IC;\n\ninterface IA {\n    void f(IB b);\n}\n";
+        let edits = organize_imports(content).unwrap();
+
+        assert_eq!(edits[0].range, range((2, 0), (4, 0)));
+        assert_eq!(edits[0].new_text, "import b.IB;\n");
+    }
+
+    #[test]
+    fn organize_imports_without_imports() {
+        let content = "package a;\n\ninterface IA {\n    void f(IB b);\n}\n";
+        let edits = organize_imports(content).unwrap();
+
+        assert_eq!(edits[0].range, range((1, 0), (1, 0)));
+        assert_eq!(edits[0].new_text, "\nimport b.IB;\n");
+    }
+
+    #[test]
+    fn organize_imports_already_organized() {
+        let content = "package a;\n\nimport b.IB;\n\nimport c.IC;\n\ninterface IA {\n    void f(IB b, IC c);\n}\n";
+        assert_eq!(organize_imports(content), None);
+    }
+
+    #[test]
+    fn organize_imports_keeps_comments() {
+        let content = "package a;\n\nimport c.IC;\n// Needed for f()\nimport b.IB;\n\ninterface IA {\n    void f(IB b, IC c);\n}\n";
+        assert_eq!(organize_imports(content), None);
+
+        let content = "package a;\n\nimport c.IC; /* for f() */\nimport b.IB;\n\ninterface IA {\n    void f(IB b, IC c);\n}\n";
+        assert_eq!(organize_imports(content), None);
+    }
+
+    fn quick_fixes(files: &[(&str, &str)], path: &str, range: lsp_types::Range) -> Vec<CodeAction> {
+        let (global_state, _receiver) = indexing::index_test_files(files);
+        let ast = global_state.file_results[&uri(path)].ast.as_ref().unwrap();
+
+        get_quick_fixes(&global_state, &uri(path), ast, &range, &[])
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action,
+                CodeActionOrCommand::Command(_) => panic!("unexpected command"),
+            })
+            .collect()
+    }
+
+    // Whole content of the test files
+    fn everything() -> lsp_types::Range {
+        range((0, 0), (100, 0))
+    }
+
+    fn get_renamed_uri(edit: &WorkspaceEdit) -> lsp_types::Url {
+        match &edit.document_changes {
+            Some(lsp_types::DocumentChanges::Operations(ops)) => match &ops[..] {
+                [lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Rename(rename))] => {
+                    rename.new_uri.clone()
+                }
+                _ => panic!("unexpected operations: {:?}", ops),
+            },
+            _ => panic!("unexpected edit: {:?}", edit),
+        }
+    }
+
+    #[test]
+    fn add_import_of_unique_item() {
+        let content = "package a;\n\nimport c.IC;\n\ninterface IA {\n    void f(IB b, IC c);\n}\n";
+        let actions = quick_fixes(&[("a/IA.aidl", content), IB, IC], "a/IA.aidl", everything());
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Import `b.IB`");
+        assert_eq!(actions[0].kind, Some(CodeActionKind::QUICKFIX));
+
+        // Inserted before the next import in alphabetical order
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "a/IA.aidl");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range, range((2, 0), (2, 0)));
+        assert_eq!(edits[0].new_text, "import b.IB;\n");
+    }
+
+    #[test]
+    fn add_import_without_imports() {
+        let content = "package a;\n\ninterface IA {\n    void f(IB b);\n}\n";
+        let actions = quick_fixes(&[("a/IA.aidl", content), IB], "a/IA.aidl", everything());
+
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "a/IA.aidl");
+        assert_eq!(edits[0].range, range((1, 0), (1, 0)));
+        assert_eq!(edits[0].new_text, "\nimport b.IB;\n");
+    }
+
+    #[test]
+    fn add_import_of_ambiguous_item() {
+        let content = "package a;\n\ninterface IA {\n    void f(IB b);\n}\n";
+        let other_ib = ("c/IB.aidl", "package c;\ninterface IB {}\n");
+        let actions = quick_fixes(
+            &[("a/IA.aidl", content), IB, other_ib],
+            "a/IA.aidl",
+            everything(),
+        );
+
+        assert!(actions.is_empty(), "{:?}", actions);
+    }
+
+    #[test]
+    fn add_import_outside_of_range() {
+        let content = "package a;\n\ninterface IA {\n    void f(IB b);\n}\n";
+        let actions = quick_fixes(
+            &[("a/IA.aidl", content), IB],
+            "a/IA.aidl",
+            range((0, 0), (2, 0)),
+        );
+
+        assert!(actions.is_empty(), "{:?}", actions);
+    }
+
+    #[test]
+    fn remove_unused_import() {
+        let content =
+            "package a;\n\nimport b.IB;\nimport c.IC;\n\ninterface IA {\n    void f(IC c);\n}\n";
+        let actions = quick_fixes(&[("a/IA.aidl", content), IB, IC], "a/IA.aidl", everything());
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Remove unused import `b.IB`");
+
+        // The whole line is removed
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "a/IA.aidl");
+        assert_eq!(edits[0].range, range((2, 0), (3, 0)));
+        assert_eq!(edits[0].new_text, "");
+    }
+
+    #[test]
+    fn fix_package_and_move_file() {
+        let content = "package com.x;\n\ninterface IA {}\n";
+        let ib = ("com/b/IB.aidl", "package com.b;\ninterface IB {}\n");
+        let actions = quick_fixes(
+            &[("com/a/IA.aidl", content), ib],
+            "com/a/IA.aidl",
+            everything(),
+        );
+
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].title, "Change package to `com.a`");
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "com/a/IA.aidl");
+        assert_eq!(edits[0].range, range((0, 8), (0, 13)));
+        assert_eq!(edits[0].new_text, "com.a");
+
+        assert_eq!(actions[1].title, "Move file to `com/x/IA.aidl`");
+        assert_eq!(
+            get_renamed_uri(actions[1].edit.as_ref().unwrap()),
+            uri("com/x/IA.aidl")
+        );
+    }
+
+    #[test]
+    fn rename_file() {
+        let content = "package a;\n\ninterface IA {}\n";
+        let actions = quick_fixes(&[("a/IFoo.aidl", content)], "a/IFoo.aidl", everything());
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Rename file to `IA.aidl`");
+        assert_eq!(
+            get_renamed_uri(actions[0].edit.as_ref().unwrap()),
+            uri("a/IA.aidl")
+        );
+    }
+
+    #[test]
+    fn add_missing_direction() {
+        let content = "package a;\n\ninterface IA {\n    void f(int a, int[] b, in int[] c);\n}\n";
+        let actions = quick_fixes(&[("a/IA.aidl", content)], "a/IA.aidl", everything());
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Add `in` direction");
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "a/IA.aidl");
+        assert_eq!(edits[0].range, range((3, 18), (3, 18)));
+        assert_eq!(edits[0].new_text, "in ");
+    }
+
+    #[test]
+    fn fix_oneway_return_type() {
+        let content =
+            "package a;\n\ninterface IA {\n    oneway int f();\n    oneway void g();\n}\n";
+        let actions = quick_fixes(&[("a/IA.aidl", content)], "a/IA.aidl", everything());
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Change return type to `void`");
+        let edits = get_edits(actions[0].edit.as_ref().unwrap(), "a/IA.aidl");
+        assert_eq!(edits[0].range, range((3, 11), (3, 14)));
+        assert_eq!(edits[0].new_text, "void");
+    }
+}
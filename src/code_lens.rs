@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+use aidl_parser::ast;
+
+use crate::utils;
+
+/// Code lenses of an interface: number of methods and latest frozen version (if any)
+pub fn get_code_lenses(uri: &lsp_types::Url, ast: &ast::Aidl) -> Vec<lsp_types::CodeLens> {
+    let interface = match &ast.item {
+        ast::Item::Interface(i) => i,
+        _ => return Vec::new(),
+    };
+
+    let range = utils::to_lsp_range(&interface.symbol_range);
+    let create_lens = |title: String| lsp_types::CodeLens {
+        range,
+        command: Some(lsp_types::Command {
+            title,
+            command: String::new(),
+            arguments: None,
+        }),
+        data: None,
+    };
+
+    let method_count = interface
+        .elements
+        .iter()
+        .filter(|el| el.as_method().is_some())
+        .count();
+
+    let mut lenses = Vec::from([create_lens(match method_count {
+        1 => String::from("1 method"),
+        n => format!("{} methods", n),
+    })]);
+
+    // Virtual documents have no frozen version
+    let frozen_version = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| get_frozen_version(&path, ast));
+    if let Some(version) = frozen_version {
+        lenses.push(create_lens(format!("frozen version: {}", version)));
+    }
+
+    lenses
+}
+
+/// Latest frozen version of an item, i.e. the highest version of the `aidl_api/` snapshots
+/// containing the item (e.g. `<module>/aidl_api/<module name>/2/a/b/IFoo.aidl`)
+pub fn get_frozen_version(path: &Path, ast: &ast::Aidl) -> Option<u32> {
+    let relative_path: PathBuf = ast
+        .package
+        .name
+        .split('.')
+        .chain(std::iter::once(
+            format!("{}.aidl", ast.item.get_name()).as_str(),
+        ))
+        .collect();
+
+    path.ancestors()
+        .skip(1)
+        .map(|dir| dir.join("aidl_api"))
+        .find(|dir| dir.is_dir())
+        .and_then(|aidl_api_dir| {
+            walkdir::WalkDir::new(aidl_api_dir)
+                .min_depth(2)
+                .max_depth(2)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_dir())
+                .filter(|e| e.path().join(&relative_path).is_file())
+                .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+                .max()
+        })
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+
+use crate::backends::Backend;
+
+/// Name of the project configuration file (in the workspace root)
+pub const PROJECT_CONFIG_FILE: &str = ".aidl-lsp.toml";
+
+/// Section of the client settings (`workspace/configuration`)
+pub const CLIENT_CONFIG_SECTION: &str = "aidl";
+
+/// Server settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Directories containing the package hierarchies (absolute or relative to the
+    /// workspace root), taking priority over the roots inferred from the AIDL files
+    pub import_roots: Vec<PathBuf>,
+
+    /// Files and directories which are not indexed (e.g. `out`, `build/**/generated`):
+    /// directory or file names, or paths relative to the workspace root (`*` and `**`
+    /// wildcards)
+    ///
+    /// By default, the frozen API snapshots (`aidl_api`) are excluded as they duplicate
+    /// the definitions of the items.
+    pub excludes: Vec<String>,
+
+    /// Maximum level of the logs (`off`, `error`, `warn`, `info`, `debug` or `trace`),
+    /// overriding the level given on the command line or by `AIDL_LSP_LOG`
+    pub log_level: Option<String>,
+
+    /// Severity of the diagnostics of the workspace checks
+    pub lints: Lints,
+
+    /// Options of the generated code (e.g. organized imports)
+    pub formatter: Formatter,
+
+    /// Backends whose generated signatures are shown in hovers (e.g. `["java", "cpp"]`)
+    pub hover_backends: Vec<Backend>,
+
+    /// Directory where a reproducer is written when the server panics (e.g. for bug
+    /// reports), disabled if not set
+    pub crash_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            import_roots: Vec::new(),
+            excludes: vec!["aidl_api".to_owned()],
+            log_level: None,
+            lints: Lints::default(),
+            formatter: Formatter::default(),
+            hover_backends: Vec::new(),
+            crash_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Lints {
+    /// Items defined in several files
+    pub duplicated_items: LintSeverity,
+    /// Package or file name not matching the location of the file
+    pub file_locations: LintSeverity,
+    /// Unresolved references in doc comments
+    pub doc_links: LintSeverity,
+    /// Items which are never referenced nor imported
+    pub unused_items: LintSeverity,
+}
+
+impl Default for Lints {
+    fn default() -> Self {
+        Lints {
+            duplicated_items: LintSeverity::Error,
+            file_locations: LintSeverity::Error,
+            doc_links: LintSeverity::Warning,
+            unused_items: LintSeverity::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LintSeverity {
+    /// Severity of the diagnostics (`None` if the lint is disabled)
+    pub fn to_lsp(self) -> Option<lsp_types::DiagnosticSeverity> {
+        match self {
+            LintSeverity::Off => None,
+            LintSeverity::Hint => Some(lsp_types::DiagnosticSeverity::HINT),
+            LintSeverity::Info => Some(lsp_types::DiagnosticSeverity::INFORMATION),
+            LintSeverity::Warning => Some(lsp_types::DiagnosticSeverity::WARNING),
+            LintSeverity::Error => Some(lsp_types::DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Formatter {
+    /// Separate the imports of different top-level packages with an empty line
+    pub group_imports: bool,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            group_imports: true,
+        }
+    }
+}
+
+impl Config {
+    /// Whether the files of the workspace must be indexed again to apply the new config
+    pub fn requires_reindexing(&self, new_config: &Config) -> bool {
+        self.import_roots != new_config.import_roots || self.excludes != new_config.excludes
+    }
+}
+
+/// The sources of the settings, by increasing priority: `initializationOptions`, client
+/// settings (`workspace/configuration` or `workspace/didChangeConfiguration`) and
+/// project configuration file
+///
+/// Note: the project file has the highest priority because clients typically send the
+/// default values of all their settings.
+#[derive(Debug, Default)]
+pub struct ConfigSources {
+    pub initialization_options: Option<serde_json::Value>,
+    pub client_settings: Option<serde_json::Value>,
+    pub project_file: Option<serde_json::Value>,
+}
+
+impl ConfigSources {
+    /// Merge the sources into the resulting config (error if a value is invalid)
+    pub fn resolve(&self) -> Result<Config> {
+        let mut merged = serde_json::Value::Object(serde_json::Map::new());
+        for source in [
+            &self.initialization_options,
+            &self.client_settings,
+            &self.project_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            merge(&mut merged, source);
+        }
+
+        serde_json::from_value(merged).context("Invalid settings")
+    }
+}
+
+// Recursive merge of JSON objects (null values are ignored, e.g. unset client settings)
+fn merge(target: &mut serde_json::Value, source: &serde_json::Value) {
+    match (target, source) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(key) {
+                    Some(target_value) => merge(target_value, value),
+                    None if !value.is_null() => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                    None => (),
+                }
+            }
+        }
+        (_, serde_json::Value::Null) => (),
+        (target, source) => *target = source.clone(),
+    }
+}
+
+/// Load the project configuration file of the workspace (`None` if there is none)
+///
+/// The keys are written in snake case (e.g. `import_roots`).
+pub fn load_project_file(root_path: &Path) -> Result<Option<serde_json::Value>> {
+    let path = root_path.join(PROJECT_CONFIG_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Could not read {:?}", path)),
+    };
+
+    let value: serde_json::Value =
+        toml::from_str(&text).with_context(|| format!("Invalid {:?}", path))?;
+
+    Ok(Some(to_camel_case_keys(value)))
+}
+
+fn to_camel_case_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| (to_camel_case(&key), to_camel_case_keys(value)))
+            .collect(),
+        value => value,
+    }
+}
+
+fn to_camel_case(key: &str) -> String {
+    let mut parts = key.split('_');
+    let first = parts.next().unwrap_or_default().to_owned();
+
+    parts.fold(first, |mut s, part| {
+        let mut chars = part.chars();
+        if let Some(c) = chars.next() {
+            s.extend(c.to_uppercase());
+            s.push_str(chars.as_str());
+        }
+        s
+    })
+}
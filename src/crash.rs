@@ -0,0 +1,79 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Run the function and catch its panic (if any), returning the panic message
+///
+/// Note: the function is assumed to be unwind-safe, the callers must not use the state it
+/// mutated after a panic (e.g. the workspace is indexed again)
+pub fn catch_unwind<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"))
+    })
+}
+
+/// Write a reproducer of a panic into the crash directory: the message which caused it
+/// (`message.json`) and the content of the document (if any)
+pub fn write_crash_report(
+    global_state: &GlobalState,
+    crash_dir: &Path,
+    method: &str,
+    params: &serde_json::Value,
+    panic_message: &str,
+) -> Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let report_dir = crash_dir.join(format!("{}-{}", timestamp, method.replace('/', "_")));
+    std::fs::create_dir_all(&report_dir)?;
+
+    let message = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "method": method,
+        "params": params,
+        "panic": panic_message,
+    });
+    std::fs::write(
+        report_dir.join("message.json"),
+        serde_json::to_string_pretty(&message)?,
+    )?;
+
+    // Content of the document as seen by the server
+    if let Some(uri) = get_document_uri(params).map(|uri| utils::normalize_uri(&uri)) {
+        let path = uri.to_file_path().ok();
+        let content = match (global_state.file_contents.get(&uri), &path) {
+            (Some(content), _) => Some(content.clone()),
+            (None, Some(path)) => std::fs::read_to_string(path).ok(),
+            (None, None) => None,
+        };
+
+        // Virtual documents (e.g. `untitled:Untitled-1`) are named after their URI
+        let file_name = match path.as_ref().and_then(|p| p.file_name()) {
+            Some(file_name) => file_name.to_string_lossy().into_owned(),
+            None => uri.path().replace(['/', '\\', ':'], "_"),
+        };
+
+        if let Some(content) = content {
+            std::fs::write(report_dir.join(file_name), content)?;
+        }
+    }
+
+    Ok(report_dir)
+}
+
+/// URI of the document targeted by a request or notification (if any)
+pub fn get_document_uri(params: &serde_json::Value) -> Option<lsp_types::Url> {
+    params["textDocument"]["uri"]
+        .as_str()
+        .and_then(|uri| lsp_types::Url::parse(uri).ok())
+}
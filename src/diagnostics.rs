@@ -0,0 +1,332 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use anyhow::Result;
+use lsp_types::notification::Notification;
+
+use crate::lsp_ext::{
+    DocumentDiagnosticReport, FullDocumentDiagnosticReport, UnchangedDocumentDiagnosticReport,
+    WorkspaceDiagnosticReport, WorkspaceDocumentDiagnosticReport,
+};
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Diagnostics of a file: the ones of the parser and the ones of the workspace checks
+pub fn get_file_diagnostics(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+) -> Result<Vec<lsp_types::Diagnostic>> {
+    let res = utils::get_file_results(global_state, uri)?;
+
+    let workspace_diagnostics = global_state
+        .workspace_diagnostics
+        .get(uri)
+        .into_iter()
+        .flatten()
+        .cloned();
+
+    let diagnostics = res
+        .diagnostics
+        .iter()
+        .map(|d| to_lsp_diagnostic(uri, d))
+        .chain(workspace_diagnostics)
+        .collect();
+
+    Ok(diagnostics)
+}
+
+fn to_lsp_diagnostic(
+    uri: &lsp_types::Url,
+    d: &aidl_parser::diagnostic::Diagnostic,
+) -> lsp_types::Diagnostic {
+    let main_location = lsp_types::Location {
+        uri: uri.clone(),
+        range: utils::to_lsp_range(&d.range),
+    };
+
+    let mut related_infos = Vec::new();
+
+    // Note: do not add the context message which is redundant with the main message
+    //if let Some(ctx_msg) = &d.context_message {
+    //    related_infos.push(lsp_types::DiagnosticRelatedInformation {
+    //        location: main_location.clone(),
+    //        message: ctx_msg.clone(),
+    //    });
+    //}
+
+    if let Some(hint) = &d.hint {
+        related_infos.push(lsp_types::DiagnosticRelatedInformation {
+            location: main_location,
+            message: hint.clone(),
+        });
+    }
+
+    let related_infos = related_infos
+        .into_iter()
+        .chain(
+            d.related_infos
+                .iter()
+                .map(|ri| lsp_types::DiagnosticRelatedInformation {
+                    location: lsp_types::Location {
+                        uri: uri.clone(),
+                        range: utils::to_lsp_range(&ri.range),
+                    },
+                    message: ri.message.clone(),
+                }),
+        )
+        .collect();
+
+    lsp_types::Diagnostic::new(
+        utils::to_lsp_range(&d.range),
+        Some(match d.kind {
+            aidl_parser::diagnostic::DiagnosticKind::Error => lsp_types::DiagnosticSeverity::ERROR,
+            aidl_parser::diagnostic::DiagnosticKind::Warning => {
+                lsp_types::DiagnosticSeverity::WARNING
+            }
+        }),
+        Some(lsp_types::NumberOrString::String("aidl".to_owned())),
+        None,
+        d.message.clone(),
+        Some(related_infos),
+        None,
+    )
+}
+
+/// Push the diagnostics of all the files and virtual documents to the client (when it
+/// does not pull them)
+pub fn publish_diagnostics(global_state: &GlobalState) -> Result<()> {
+    let uris = global_state
+        .file_results
+        .keys()
+        .chain(global_state.virtual_documents.keys());
+
+    for uri in uris {
+        let diagnostics = get_file_diagnostics(global_state, uri)?;
+        send_diagnostics(global_state, uri.clone(), diagnostics);
+    }
+
+    Ok(())
+}
+
+/// Clear the diagnostics of a document which is not analyzed anymore
+pub fn publish_empty_diagnostics(global_state: &GlobalState, uri: lsp_types::Url) {
+    send_diagnostics(global_state, uri, Vec::new());
+}
+
+fn send_diagnostics(
+    global_state: &GlobalState,
+    uri: lsp_types::Url,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+) {
+    let notif = lsp_server::Notification::new(
+        lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
+        lsp_types::PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        },
+    );
+    global_state
+        .sender
+        .send(lsp_server::Message::Notification(notif))
+        .unwrap();
+}
+
+/// Diagnostic report of a file (`unchanged` if the diagnostics match the previous result)
+pub fn get_document_report(
+    global_state: &GlobalState,
+    uri: &lsp_types::Url,
+    previous_result_id: Option<&str>,
+) -> Result<DocumentDiagnosticReport> {
+    let diagnostics = get_file_diagnostics(global_state, uri)?;
+    let result_id = get_result_id(&diagnostics);
+
+    if previous_result_id == Some(result_id.as_str()) {
+        return Ok(DocumentDiagnosticReport::Unchanged(
+            UnchangedDocumentDiagnosticReport { result_id },
+        ));
+    }
+
+    Ok(DocumentDiagnosticReport::Full(
+        FullDocumentDiagnosticReport {
+            result_id: Some(result_id),
+            items: diagnostics,
+        },
+    ))
+}
+
+/// Diagnostic reports of the files of the workspace which changed since the previous
+/// results (by URI)
+pub fn get_workspace_report(
+    global_state: &GlobalState,
+    previous_result_ids: &HashMap<lsp_types::Url, String>,
+) -> Result<WorkspaceDiagnosticReport> {
+    let mut items = Vec::new();
+
+    for uri in global_state.file_results.keys() {
+        let previous_result_id = previous_result_ids.get(uri).map(String::as_str);
+
+        match get_document_report(global_state, uri, previous_result_id)? {
+            DocumentDiagnosticReport::Unchanged(_) => (),
+            report => items.push(WorkspaceDocumentDiagnosticReport {
+                uri: uri.clone(),
+                version: None,
+                report,
+            }),
+        }
+    }
+
+    Ok(WorkspaceDiagnosticReport { items })
+}
+
+/// Answer the pending `workspace/diagnostic` request (if any), after a change of the
+/// workspace
+pub fn complete_workspace_report(global_state: &mut GlobalState) -> Result<()> {
+    let (id, previous_result_ids) = match global_state.pending_workspace_diagnostics.take() {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+
+    let report = get_workspace_report(global_state, &previous_result_ids)?;
+    if report.items.is_empty() {
+        // Still no change: keep waiting
+        global_state.pending_workspace_diagnostics = Some((id, previous_result_ids));
+        return Ok(());
+    }
+
+    global_state
+        .sender
+        .send(lsp_server::Message::Response(lsp_server::Response::new_ok(
+            id, report,
+        )))
+        .unwrap();
+
+    Ok(())
+}
+
+// Result ID of a report, derived from its content
+fn get_result_id(diagnostics: &[lsp_types::Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+
+    format!("{:x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handlers, indexing, lsp_ext};
+
+    const IA: &str = "package a;\ninterface IA {\n    void f(int[] a);\n}\n";
+    const IB: &str = "package a;\ninterface IB {}\n";
+
+    fn uri(path: &str) -> lsp_types::Url {
+        utils::path_to_uri(&std::path::Path::new("/ws").join(path)).unwrap()
+    }
+
+    fn get_full_report(report: DocumentDiagnosticReport) -> FullDocumentDiagnosticReport {
+        match report {
+            DocumentDiagnosticReport::Full(report) => report,
+            DocumentDiagnosticReport::Unchanged(report) => panic!("unchanged: {:?}", report),
+        }
+    }
+
+    #[test]
+    fn unchanged_report() {
+        let (mut global_state, _receiver) = indexing::index_test_files(&[("a/IA.aidl", IA)]);
+        global_state.pull_diagnostics = true;
+
+        let report =
+            get_full_report(get_document_report(&global_state, &uri("a/IA.aidl"), None).unwrap());
+        assert_eq!(report.items.len(), 1);
+        let result_id = report.result_id.unwrap();
+
+        match get_document_report(&global_state, &uri("a/IA.aidl"), Some(&result_id)).unwrap() {
+            DocumentDiagnosticReport::Unchanged(report) => assert_eq!(report.result_id, result_id),
+            DocumentDiagnosticReport::Full(report) => panic!("full: {:?}", report),
+        }
+
+        // A change of the content which does not change the diagnostics
+        let new_content = format!("{}// end\n", IA);
+        indexing::update_content(&mut global_state, &uri("a/IA.aidl"), &new_content).unwrap();
+
+        let report = get_document_report(&global_state, &uri("a/IA.aidl"), Some(&result_id));
+        assert!(matches!(
+            report.unwrap(),
+            DocumentDiagnosticReport::Unchanged(_)
+        ));
+    }
+
+    #[test]
+    fn changed_report() {
+        let (mut global_state, _receiver) = indexing::index_test_files(&[("a/IA.aidl", IA)]);
+        global_state.pull_diagnostics = true;
+
+        let report =
+            get_full_report(get_document_report(&global_state, &uri("a/IA.aidl"), None).unwrap());
+        let result_id = report.result_id.unwrap();
+
+        let fixed_content = IA.replace("int[] a", "in int[] a");
+        indexing::update_content(&mut global_state, &uri("a/IA.aidl"), &fixed_content).unwrap();
+
+        let report = get_full_report(
+            get_document_report(&global_state, &uri("a/IA.aidl"), Some(&result_id)).unwrap(),
+        );
+        assert!(report.items.is_empty());
+        assert_ne!(report.result_id, Some(result_id));
+    }
+
+    #[test]
+    fn deferred_workspace_report() {
+        let (mut global_state, receiver) =
+            indexing::index_test_files(&[("a/IA.aidl", IA), ("a/IB.aidl", IB)]);
+        global_state.pull_diagnostics = true;
+
+        let previous_result_ids: Vec<lsp_ext::PreviousResultId> = ["a/IA.aidl", "a/IB.aidl"]
+            .iter()
+            .map(|path| {
+                let report = get_document_report(&global_state, &uri(path), None).unwrap();
+                lsp_ext::PreviousResultId {
+                    uri: uri(path),
+                    value: get_full_report(report).result_id.unwrap(),
+                }
+            })
+            .collect();
+
+        // Nothing changed: the request is answered after the next change
+        let id = lsp_server::RequestId::from(42);
+        let params = lsp_ext::WorkspaceDiagnosticParams {
+            identifier: None,
+            previous_result_ids,
+        };
+        let report = handlers::handle_workspace_diagnostic(&mut global_state, id.clone(), params);
+        assert!(report.unwrap().is_none());
+        assert!(receiver.try_recv().is_err());
+
+        // A change without new diagnostics keeps the request pending
+        indexing::update_content(
+            &mut global_state,
+            &uri("a/IB.aidl"),
+            &IB.replace("{}", "{ }"),
+        )
+        .unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        let fixed_content = IA.replace("int[] a", "in int[] a");
+        indexing::update_content(&mut global_state, &uri("a/IA.aidl"), &fixed_content).unwrap();
+
+        let response = match receiver.try_recv().unwrap() {
+            lsp_server::Message::Response(response) => response,
+            message => panic!("unexpected message: {:?}", message),
+        };
+        assert_eq!(response.id, id);
+        let report: WorkspaceDiagnosticReport =
+            serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(report.items.len(), 1);
+        assert_eq!(report.items[0].uri, uri("a/IA.aidl"));
+        assert!(global_state.pending_workspace_diagnostics.is_none());
+    }
+}
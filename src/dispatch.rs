@@ -2,8 +2,24 @@ use anyhow::Result;
 use core::fmt;
 use lsp_server::Message;
 use serde::{de::DeserializeOwned, Serialize};
+use std::time::{Duration, Instant};
 
-use crate::{error::LspError, state::GlobalState};
+use crate::{
+    crash,
+    error::LspError,
+    indexing,
+    state::{GlobalState, IndexingState},
+};
+
+// Handlers taking longer are logged
+const SLOW_HANDLER_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// Request handler which may postpone the response (see `RequestDispatcher::on_deferred`)
+type DeferredHandler<R> = fn(
+    &mut GlobalState,
+    lsp_server::RequestId,
+    <R as lsp_types::request::Request>::Params,
+) -> Result<Option<<R as lsp_types::request::Request>::Result>>;
 
 /// A visitor for routing a raw JSON request to an appropriate handler function.
 ///
@@ -24,18 +40,45 @@ impl<'a> RequestDispatcher<'a> {
         R::Params: DeserializeOwned + fmt::Debug + 'static,
         R::Result: Serialize + 'static,
     {
-        let (id, params) = match self.parse::<R>() {
+        let (id, params, raw_params) = match self.parse::<R>() {
             Some(it) => it,
             None => return Ok(self),
         };
 
-        let result = f(self.global_state, params);
+        let result = call_handler(self.global_state, R::METHOD, &raw_params, |global_state| {
+            f(global_state, params)
+        });
         let response = result_to_response::<R>(id, result);
 
         self.global_state.sender.send(Message::Response(response))?;
         Ok(self)
     }
 
+    /// Dispatches the request onto the current thread, the handler may postpone the
+    /// response (by returning `None`) and send it later with the given request ID
+    pub fn on_deferred<R>(&mut self, f: DeferredHandler<R>) -> Result<&mut Self>
+    where
+        R: lsp_types::request::Request + 'static,
+        R::Params: DeserializeOwned + fmt::Debug + 'static,
+        R::Result: Serialize + 'static,
+    {
+        let (id, params, raw_params) = match self.parse::<R>() {
+            Some(it) => it,
+            None => return Ok(self),
+        };
+
+        let result = call_handler(self.global_state, R::METHOD, &raw_params, |global_state| {
+            f(global_state, id.clone(), params)
+        });
+        let response = match result.transpose() {
+            Some(result) => result_to_response::<R>(id, result),
+            None => return Ok(self),
+        };
+
+        self.global_state.sender.send(Message::Response(response))?;
+        Ok(self)
+    }
+
     pub fn finish(&mut self) {
         if let Some(req) = self.req.take() {
             tracing::error!("unknown request: {:?}", req);
@@ -52,7 +95,8 @@ impl<'a> RequestDispatcher<'a> {
         }
     }
 
-    fn parse<R>(&mut self) -> Option<(lsp_server::RequestId, R::Params)>
+    // Parameters of the request, typed and raw (e.g. for crash reports)
+    fn parse<R>(&mut self) -> Option<(lsp_server::RequestId, R::Params, serde_json::Value)>
     where
         R: lsp_types::request::Request + 'static,
         R::Params: DeserializeOwned + fmt::Debug + 'static,
@@ -62,9 +106,9 @@ impl<'a> RequestDispatcher<'a> {
             _ => return None,
         };
 
-        let res = from_json(R::METHOD, req.params);
+        let res = from_json(R::METHOD, req.params.clone());
         match res {
-            Ok(params) => Some((req.id, params)),
+            Ok(params) => Some((req.id, params, req.params)),
             Err(err) => {
                 let response = lsp_server::Response::new_err(
                     req.id,
@@ -84,7 +128,6 @@ impl<'a> RequestDispatcher<'a> {
 pub struct NotificationDispatcher<'a> {
     pub notif: Option<lsp_server::Notification>,
     pub global_state: &'a mut GlobalState,
-    pub connection: &'a lsp_server::Connection,
 }
 
 impl<'a> NotificationDispatcher<'a> {
@@ -97,14 +140,35 @@ impl<'a> NotificationDispatcher<'a> {
             Some(it) => it,
             None => return Ok(self),
         };
-        let params = match notif.extract::<N::Params>(N::METHOD) {
-            Ok(it) => it,
-            Err(not) => {
-                self.notif = Some(not);
-                return Ok(self);
+        if notif.method != N::METHOD {
+            self.notif = Some(notif);
+            return Ok(self);
+        }
+
+        let raw_params = notif.params.clone();
+        let params = from_json::<N::Params>(N::METHOD, notif.params)?;
+
+        // Errors are reported to the user but do not stop the server
+        let result = call_handler(self.global_state, N::METHOD, &raw_params, |global_state| {
+            f(global_state, params)
+        });
+        if let Err(e) = result {
+            match e.downcast_ref::<LspError>() {
+                Some(lsp_error) if lsp_error.is_silent() => {
+                    tracing::warn!("{} failed: {}", N::METHOD, lsp_error.message);
+                }
+                lsp_error => {
+                    let message = match lsp_error {
+                        Some(lsp_error) => lsp_error.message.clone(),
+                        None => format!("{:#}", e),
+                    };
+                    tracing::error!("{} failed: {}", N::METHOD, message);
+                    self.global_state
+                        .show_message(lsp_types::MessageType::ERROR, format!("AIDL: {}", message));
+                }
             }
-        };
-        f(self.global_state, params)?;
+        }
+
         Ok(self)
     }
 
@@ -123,6 +187,74 @@ pub fn from_json<T: DeserializeOwned>(what: &'static str, json: serde_json::Valu
     Ok(res)
 }
 
+// Call the handler (in a span, measuring its latency), a panic is turned into an
+// `InternalError` (and reported in the crash directory, if configured) and the workspace
+// is indexed again
+fn call_handler<T>(
+    global_state: &mut GlobalState,
+    method: &'static str,
+    raw_params: &serde_json::Value,
+    f: impl FnOnce(&mut GlobalState) -> Result<T>,
+) -> Result<T> {
+    let _span = tracing::info_span!("handle", method).entered();
+
+    let start = Instant::now();
+    // `AssertUnwindSafe` (see `crash::catch_unwind`) is sound as the state possibly left
+    // half-mutated by a panicking handler is never observed: it is rebuilt below
+    let result = crash::catch_unwind(|| f(global_state));
+    let duration = start.elapsed();
+
+    global_state.metrics.record(method, duration);
+    if duration > SLOW_HANDLER_THRESHOLD {
+        tracing::info!("{} took {} ms", method, duration.as_millis());
+    }
+
+    let panic_message = match result {
+        Ok(result) => return result,
+        Err(panic_message) => panic_message,
+    };
+
+    let uri = crash::get_document_uri(raw_params);
+    tracing::error!(
+        "{} panicked: {} (document: {})",
+        method,
+        panic_message,
+        uri.as_ref().map(|uri| uri.as_str()).unwrap_or("none")
+    );
+    tracing::debug!("{} params: {}", method, raw_params);
+
+    if let Some(crash_dir) = &global_state.config.crash_dir {
+        match crash::write_crash_report(global_state, crash_dir, method, raw_params, &panic_message)
+        {
+            Ok(report_dir) => tracing::info!("Crash report written to {:?}", report_dir),
+            Err(e) => tracing::warn!("Could not write crash report: {}", e),
+        }
+    }
+
+    // Not indexed yet: the workspace will be indexed from scratch anyway
+    if global_state.indexing_state != IndexingState::Idle {
+        reindex_after_panic(global_state);
+    }
+
+    Err(LspError::new(
+        lsp_server::ErrorCode::InternalError,
+        format!("{} panicked: {}", method, panic_message),
+    )
+    .into())
+}
+
+// Index the workspace again (keeping the contents of the open documents), the server
+// refusing the requests if this fails too
+fn reindex_after_panic(global_state: &mut GlobalState) {
+    let result = crash::catch_unwind(|| indexing::reindex(global_state))
+        .unwrap_or_else(|panic_message| Err(anyhow::anyhow!("panic: {}", panic_message)));
+
+    if let Err(e) = result {
+        tracing::error!("Could not index the workspace after a panic: {:#}", e);
+        global_state.indexing_state = IndexingState::Error;
+    }
+}
+
 fn result_to_response<R>(
     id: lsp_server::RequestId,
     result: Result<R::Result>,
@@ -135,12 +267,53 @@ where
     match result {
         Ok(resp) => lsp_server::Response::new_ok(id, &resp),
         Err(e) => match e.downcast::<LspError>() {
-            Ok(lsp_error) => lsp_server::Response::new_err(id, lsp_error.code, lsp_error.message),
-            Err(e) => lsp_server::Response::new_err(
-                id,
-                lsp_server::ErrorCode::InternalError as i32,
-                e.to_string(),
-            ),
+            Ok(lsp_error) => {
+                if lsp_error.is_silent() {
+                    tracing::debug!("{} failed: {}", R::METHOD, lsp_error.message);
+                } else {
+                    tracing::warn!("{} failed: {}", R::METHOD, lsp_error.message);
+                }
+                lsp_server::Response::new_err(id, lsp_error.code, lsp_error.message)
+            }
+            Err(e) => {
+                tracing::error!("{} failed: {:#}", R::METHOD, e);
+                lsp_server::Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::InternalError as i32,
+                    e.to_string(),
+                )
+            }
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reindex_after_panic() {
+        let (mut global_state, _receiver) =
+            indexing::index_test_files(&[("a/IFoo.aidl", "package a;\ninterface IFoo {}\n")]);
+        let uri = global_state.file_results.keys().next().unwrap().clone();
+        global_state.open_documents.insert(uri.clone());
+
+        // The handler leaves the state half-mutated
+        let result: Result<()> =
+            call_handler(&mut global_state, "test", &serde_json::Value::Null, |gs| {
+                gs.file_results.clear();
+                gs.items_by_key.clear();
+                panic!("oops");
+            });
+
+        let err = result.unwrap_err();
+        let lsp_error = err.downcast_ref::<LspError>().unwrap();
+        assert_eq!(lsp_error.code, lsp_server::ErrorCode::InternalError as i32);
+        assert_eq!(lsp_error.message, "test panicked: oops");
+
+        // The open document is indexed again
+        assert!(global_state.indexing_state == IndexingState::Indexed);
+        assert!(global_state.file_results.contains_key(&uri));
+        assert!(global_state.items_by_key.contains_key("a.IFoo"));
+    }
+}
@@ -0,0 +1,140 @@
+use aidl_parser::symbol::Symbol;
+use aidl_parser::traverse::{self, SymbolFilter};
+use aidl_parser::{ast, traverse::find_symbol_at_line_col};
+use lsp_types::{DocumentHighlight, DocumentHighlightKind};
+
+use crate::position_encoding::PositionEncoding;
+use crate::utils;
+use crate::values::{self, Declaration};
+
+/// Occurrences of the item, constant or enum element at the given position: the
+/// declaration is marked as `Write` and the uses as `Read` (including the references in
+/// the values, e.g. `A` in `const int B = 1 << A;`)
+pub fn get_document_highlights(
+    ast: &ast::Aidl,
+    content: &str,
+    position: &lsp_types::Position,
+    encoding: PositionEncoding,
+) -> Vec<DocumentHighlight> {
+    let declarations = values::get_declarations(&ast.item, content);
+
+    // Constant or enum element in the source (the declarations with a non-literal value
+    // are not part of the AST)
+    if let Some(offset) = utils::to_offset(content, position, encoding) {
+        let contains = |t: &values::Token| t.offset <= offset && offset <= t.offset + t.text.len();
+        let references = get_value_references(ast, &declarations);
+        if let Some(name) = get_declared_names(ast, &declarations)
+            .chain(references)
+            .find(contains)
+        {
+            return get_value_highlights(ast, &declarations, name.text, content, encoding);
+        }
+    }
+
+    match find_symbol_at_line_col(ast, SymbolFilter::All, utils::from_lsp_position(position)) {
+        Some(Symbol::Type(ast::Type {
+            kind: ast::TypeKind::Resolved(key, _),
+            ..
+        })) => get_item_highlights(ast, key),
+        Some(Symbol::Import(i)) => get_item_highlights(ast, &i.get_qualified_name()),
+        Some(Symbol::Interface(..) | Symbol::Parcelable(..) | Symbol::Enum(..)) => {
+            get_item_highlights(ast, &ast.get_key())
+        }
+        Some(Symbol::Const(c, _)) => {
+            get_value_highlights(ast, &declarations, &c.name, content, encoding)
+        }
+        Some(Symbol::EnumElement(el, _)) => {
+            get_value_highlights(ast, &declarations, &el.name, content, encoding)
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Names of the constants or enum elements (the fields cannot be referenced)
+fn get_declared_names<'a, 'b>(
+    ast: &ast::Aidl,
+    declarations: &'b [Declaration<'a>],
+) -> impl Iterator<Item = values::Token<'a>> + 'b {
+    let referenceable = !matches!(ast.item, ast::Item::Parcelable(_));
+    declarations
+        .iter()
+        .filter(move |_| referenceable)
+        .map(|d| d.name)
+}
+
+// Identifiers of the values which reference a constant or enum element of the item
+fn get_value_references<'a>(
+    ast: &ast::Aidl,
+    declarations: &[Declaration<'a>],
+) -> Vec<values::Token<'a>> {
+    declarations
+        .iter()
+        .flat_map(|d| d.value.iter().flatten())
+        .filter_map(|t| values::get_referenced_name(t, ast.item.get_name()))
+        .collect()
+}
+
+fn get_value_highlights(
+    ast: &ast::Aidl,
+    declarations: &[Declaration],
+    name: &str,
+    content: &str,
+    encoding: PositionEncoding,
+) -> Vec<DocumentHighlight> {
+    let to_lsp_range = |t: &values::Token| lsp_types::Range {
+        start: utils::offset_to_lsp_position(content, t.offset, encoding),
+        end: utils::offset_to_lsp_position(content, t.offset + t.text.len(), encoding),
+    };
+
+    let declaration = get_declared_names(ast, declarations)
+        .filter(|t| t.text == name)
+        .map(|t| create_highlight(to_lsp_range(&t), DocumentHighlightKind::WRITE));
+
+    let references = get_value_references(ast, declarations)
+        .into_iter()
+        .filter(|t| t.text == name)
+        .map(|t| create_highlight(to_lsp_range(&t), DocumentHighlightKind::READ));
+
+    declaration.chain(references).collect()
+}
+
+fn get_item_highlights(ast: &ast::Aidl, key: &str) -> Vec<DocumentHighlight> {
+    let mut highlights = Vec::new();
+
+    if ast.get_key() == key {
+        highlights.push(create_highlight(
+            utils::to_lsp_range(ast.item.get_symbol_range()),
+            DocumentHighlightKind::WRITE,
+        ));
+    }
+
+    traverse::walk_symbols(ast, SymbolFilter::All, |symbol| match symbol {
+        Symbol::Import(i) if i.get_qualified_name() == key => {
+            highlights.push(create_highlight(
+                utils::to_lsp_range(&i.symbol_range),
+                DocumentHighlightKind::READ,
+            ));
+        }
+        Symbol::Type(
+            t @ ast::Type {
+                kind: ast::TypeKind::Resolved(k, _),
+                ..
+            },
+        ) if k == key => {
+            highlights.push(create_highlight(
+                utils::to_lsp_range(&t.symbol_range),
+                DocumentHighlightKind::READ,
+            ));
+        }
+        _ => (),
+    });
+
+    highlights
+}
+
+fn create_highlight(range: lsp_types::Range, kind: DocumentHighlightKind) -> DocumentHighlight {
+    DocumentHighlight {
+        range,
+        kind: Some(kind),
+    }
+}
@@ -0,0 +1,150 @@
+use aidl_parser::ast;
+use aidl_parser::traverse;
+
+use crate::position_encoding::PositionEncoding;
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Links of a file to the files defining the referenced items: imports, qualified type
+/// references and references in doc comments (`{@link Foo}` and `@see Foo`)
+pub fn get_document_links(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    content: &str,
+) -> Vec<lsp_types::DocumentLink> {
+    let mut links = Vec::new();
+
+    for import in &ast.imports {
+        links.extend(create_link(
+            global_state,
+            utils::to_lsp_range(&import.symbol_range),
+            &import.get_qualified_name(),
+        ));
+    }
+
+    traverse::walk_types(ast, |t| match &t.kind {
+        ast::TypeKind::Resolved(key, _) if t.name.contains('.') => {
+            links.extend(create_link(
+                global_state,
+                utils::to_lsp_range(&t.symbol_range),
+                key,
+            ));
+        }
+        _ => (),
+    });
+
+    for (range, reference) in get_doc_references(content, global_state.position_encoding) {
+        if let Some(key) = resolve_doc_reference(global_state, ast, reference) {
+            links.extend(create_link(global_state, range, &key));
+        }
+    }
+
+    links
+}
+
+/// References to items in doc comments (e.g. `Foo`, `com.example.Foo#bar`), with
+/// their range
+pub fn get_doc_references(
+    content: &str,
+    encoding: PositionEncoding,
+) -> Vec<(lsp_types::Range, &str)> {
+    let mut references = Vec::new();
+    let mut rest_offset = 0;
+
+    while let Some(start) = content[rest_offset..].find("/**") {
+        let comment_start = rest_offset + start;
+        let comment_end = content[comment_start..]
+            .find("*/")
+            .map(|end| comment_start + end)
+            .unwrap_or(content.len());
+        let comment = &content[comment_start..comment_end];
+
+        for tag in ["{@link ", "{@linkplain ", "@see "] {
+            for (tag_offset, _) in comment.match_indices(tag) {
+                let reference = comment[tag_offset + tag.len()..].trim_start();
+                let ref_start = comment.len() - reference.len();
+                let ref_len = reference
+                    .find(|c: char| c.is_whitespace() || c == '}' || c == '(')
+                    .unwrap_or(reference.len());
+
+                // Skip members of the current item (e.g. `#foo`) and non-references
+                // (e.g. `@see "Some text"`)
+                let reference = &reference[..ref_len];
+                if !reference.starts_with(|c: char| c.is_alphabetic()) {
+                    continue;
+                }
+
+                let start = comment_start + ref_start;
+                references.push((
+                    lsp_types::Range {
+                        start: utils::offset_to_lsp_position(content, start, encoding),
+                        end: utils::offset_to_lsp_position(content, start + ref_len, encoding),
+                    },
+                    reference,
+                ));
+            }
+        }
+
+        rest_offset = comment_end;
+    }
+
+    references
+}
+
+/// Resolve a reference of a doc comment (e.g. `Foo`, `Foo#bar`, `IFoo.CONSTANT` or
+/// `com.example.Foo`) into the key of the referenced item
+pub fn resolve_doc_reference(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    reference: &str,
+) -> Option<ast::ItemKey> {
+    let mut name = reference.split('#').next().unwrap_or_default();
+
+    loop {
+        let (first_segment, rest) = match name.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+
+        let candidates = [
+            // Qualified name
+            Some(name.to_owned()),
+            // Imported item (e.g. `Foo` or `Foo.Bar`)
+            ast.imports
+                .iter()
+                .find(|i| i.name == first_segment)
+                .map(|i| match rest {
+                    Some(rest) => format!("{}.{}", i.get_qualified_name(), rest),
+                    None => i.get_qualified_name(),
+                }),
+            // Item of the same package
+            Some(format!("{}.{}", ast.package.name, name)),
+        ];
+
+        if let Some(key) = candidates
+            .into_iter()
+            .flatten()
+            .find(|key| global_state.items_by_key.contains_key(key))
+        {
+            return Some(key);
+        }
+
+        // The last segment may be a member (e.g. `IFoo.CONSTANT`)
+        name = name.rsplit_once('.')?.0;
+    }
+}
+
+fn create_link(
+    global_state: &GlobalState,
+    range: lsp_types::Range,
+    key: &str,
+) -> Option<lsp_types::DocumentLink> {
+    let uri = global_state.items_by_key.get(key)?;
+
+    Some(lsp_types::DocumentLink {
+        range,
+        target: Some(uri.clone()),
+        tooltip: Some(key.to_owned()),
+        data: None,
+    })
+}
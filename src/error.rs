@@ -1,11 +1,68 @@
 use core::fmt;
 
+use lsp_server::ErrorCode;
+
+use crate::state::IndexingState;
+
 #[derive(Debug)]
 pub struct LspError {
     pub code: i32,
     pub message: String,
 }
 
+impl LspError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        LspError {
+            code: code as i32,
+            message: message.into(),
+        }
+    }
+
+    /// The workspace is not indexed: while indexing, the client may retry later
+    /// (`ContentModified`), otherwise the server is not usable (`ServerNotInitialized`)
+    pub fn not_indexed(indexing_state: &IndexingState, what: &str) -> Self {
+        match indexing_state {
+            IndexingState::Idle | IndexingState::Indexing => LspError::new(
+                ErrorCode::ContentModified,
+                format!("Cannot handle {}: workspace is being indexed", what),
+            ),
+            IndexingState::Indexed | IndexingState::Error => LspError::new(
+                ErrorCode::ServerNotInitialized,
+                format!("Cannot handle {}: workspace has not been indexed!", what),
+            ),
+        }
+    }
+
+    /// The document is not part of the workspace (or has not been opened)
+    pub fn unknown_document(document: impl fmt::Display) -> Self {
+        LspError::new(
+            ErrorCode::InvalidParams,
+            format!("Unknown document: `{}`", document),
+        )
+    }
+
+    /// The URI cannot be handled (e.g. not a file)
+    pub fn invalid_uri(uri: &lsp_types::Url) -> Self {
+        LspError::new(ErrorCode::InvalidParams, format!("Invalid URI: {}", uri))
+    }
+
+    /// The request has been cancelled by the client
+    pub fn cancelled() -> Self {
+        LspError::new(ErrorCode::RequestCanceled, "Request cancelled")
+    }
+
+    /// The error is expected (e.g. the client retries later) and should not be reported
+    /// to the user
+    pub fn is_silent(&self) -> bool {
+        [
+            ErrorCode::ContentModified as i32,
+            ErrorCode::RequestCanceled as i32,
+            ErrorCode::ServerNotInitialized as i32,
+        ]
+        .contains(&self.code)
+    }
+}
+
 impl fmt::Display for LspError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
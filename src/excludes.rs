@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Compiled exclude patterns (`excludes` setting)
+///
+/// A pattern without separator matches a file or directory name anywhere in the
+/// workspace (e.g. `out`), other patterns match a path relative to the workspace root
+/// (e.g. `build/**/generated`). The files located in a matching directory are excluded.
+#[derive(Debug, Default)]
+pub struct Excludes {
+    globs: GlobSet,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let trimmed = pattern.trim_start_matches("./").trim_matches('/');
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let glob = if trimmed.contains('/') {
+                trimmed.to_owned()
+            } else {
+                format!("**/{}", trimmed)
+            };
+            let glob = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid exclude pattern {:?}", pattern))?;
+            builder.add(glob);
+        }
+
+        Ok(Excludes {
+            globs: builder.build()?,
+        })
+    }
+
+    /// Whether the path (or one of its parent directories in the root) is excluded
+    pub fn is_excluded(&self, root_path: &Path, path: &Path) -> bool {
+        if self.globs.is_empty() {
+            return false;
+        }
+
+        match path.strip_prefix(root_path) {
+            Ok(relative_path) => relative_path
+                .ancestors()
+                .take_while(|p| !p.as_os_str().is_empty())
+                .any(|p| self.globs.is_match(p)),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_excluded(patterns: &[&str], path: &str) -> bool {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        let excludes = Excludes::new(&patterns).unwrap();
+        excludes.is_excluded(Path::new("/ws"), &Path::new("/ws").join(path))
+    }
+
+    #[test]
+    fn name() {
+        assert!(is_excluded(&["out"], "out"));
+        assert!(is_excluded(&["out"], "out/com/a/IA.aidl"));
+        assert!(is_excluded(&["out"], "a/b/out/IA.aidl"));
+        assert!(is_excluded(&["IA.aidl"], "com/a/IA.aidl"));
+        assert!(!is_excluded(&["out"], "output/IA.aidl"));
+        assert!(!is_excluded(&["out"], "com/a/IA.aidl"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(is_excluded(&["*.gen.aidl"], "com/a/IA.gen.aidl"));
+        assert!(is_excluded(&["build-*"], "build-debug/IA.aidl"));
+        assert!(is_excluded(&["src/*/gen"], "src/main/gen/IA.aidl"));
+        assert!(!is_excluded(&["src/*/gen"], "src/main/java/gen/IA.aidl"));
+        assert!(!is_excluded(&["*.gen.aidl"], "com/a/IA.aidl"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(is_excluded(
+            &["build/**/generated"],
+            "build/generated/IA.aidl"
+        ));
+        assert!(is_excluded(
+            &["build/**/generated"],
+            "build/a/b/generated/IA.aidl"
+        ));
+        assert!(is_excluded(&["**/test"], "a/test/IA.aidl"));
+        assert!(!is_excluded(
+            &["build/**/generated"],
+            "src/generated/IA.aidl"
+        ));
+    }
+
+    #[test]
+    fn relative_path() {
+        assert!(is_excluded(&["com/a"], "com/a/IA.aidl"));
+        assert!(is_excluded(&["./com/a/"], "com/a/IA.aidl"));
+        assert!(!is_excluded(&["com/a"], "src/com/a/IA.aidl"));
+        assert!(!is_excluded(&["com/a"], "com/ab/IA.aidl"));
+    }
+
+    #[test]
+    fn outside_root() {
+        let excludes = Excludes::new(&["out".to_owned()]).unwrap();
+        assert!(!excludes.is_excluded(Path::new("/ws"), Path::new("/other/out/IA.aidl")));
+        assert!(!is_excluded(&[], "out/IA.aidl"));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(Excludes::new(&["a[".to_owned()]).is_err());
+    }
+}
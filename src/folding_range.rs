@@ -0,0 +1,147 @@
+use aidl_parser::ast;
+use aidl_parser::traverse::{self, SymbolFilter};
+use lsp_types::{FoldingRange, FoldingRangeKind};
+
+/// Folding ranges of a file: item body (and multi-line elements), contiguous imports,
+/// multi-line comments and multi-line annotation parameter lists
+pub fn get_folding_ranges(ast: &ast::Aidl, content: &str) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+
+    // Item body and multi-line elements (e.g. methods with many arguments), starting
+    // from the name since the full range may start before (e.g. after the annotations)
+    traverse::walk_symbols(ast, SymbolFilter::ItemsAndItemElements, |symbol| {
+        push_range(
+            &mut ranges,
+            line_of(&symbol.get_range().start),
+            line_of(&symbol.get_full_range().end),
+            None,
+        );
+    });
+
+    // Contiguous imports
+    let mut import_lines = ast
+        .imports
+        .iter()
+        .map(|i| (line_of(&i.full_range.start), line_of(&i.full_range.end)));
+    if let Some((mut block_start, mut block_end)) = import_lines.next() {
+        for (start, end) in import_lines {
+            if start > block_end + 1 {
+                push_range(
+                    &mut ranges,
+                    block_start,
+                    block_end,
+                    Some(FoldingRangeKind::Imports),
+                );
+                block_start = start;
+            }
+            block_end = end;
+        }
+        push_range(
+            &mut ranges,
+            block_start,
+            block_end,
+            Some(FoldingRangeKind::Imports),
+        );
+    }
+
+    // Comments and annotation parameter lists, which are not part of the AST
+    for (start, end, kind) in scan_text_blocks(content) {
+        push_range(&mut ranges, start, end, kind);
+    }
+
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
+
+fn line_of(position: &ast::Position) -> u32 {
+    position.line_col.0 as u32 - 1
+}
+
+fn push_range(
+    ranges: &mut Vec<FoldingRange>,
+    start_line: u32,
+    end_line: u32,
+    kind: Option<FoldingRangeKind>,
+) {
+    if end_line > start_line {
+        ranges.push(FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind,
+        });
+    }
+}
+
+// Lines (start, end) of the multi-line comments and annotation parameter lists
+fn scan_text_blocks(content: &str) -> Vec<(u32, u32, Option<FoldingRangeKind>)> {
+    let mut blocks = Vec::new();
+    let mut line = 0;
+    let mut chars = content.chars().peekable();
+
+    // Start line of the current annotation parameter list and nesting depth
+    let mut annotation: Option<(u32, usize)> = None;
+    let mut after_annotation_name = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let start = line;
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    } else if c == '/' && previous == '*' {
+                        break;
+                    }
+                    previous = c;
+                }
+                blocks.push((start, line, Some(FoldingRangeKind::Comment)));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+                line += 1;
+            }
+            '"' => {
+                for c in chars.by_ref() {
+                    match c {
+                        '"' => break,
+                        '\n' => line += 1,
+                        _ => (),
+                    }
+                }
+            }
+            '@' => {
+                while chars
+                    .next_if(|c| c.is_alphanumeric() || *c == '_')
+                    .is_some()
+                {}
+                after_annotation_name = true;
+                continue;
+            }
+            '(' => match &mut annotation {
+                Some((_, depth)) => *depth += 1,
+                None if after_annotation_name => annotation = Some((line, 0)),
+                None => (),
+            },
+            ')' => match &mut annotation {
+                Some((start, 0)) => {
+                    blocks.push((*start, line, None));
+                    annotation = None;
+                }
+                Some((_, depth)) => *depth -= 1,
+                None => (),
+            },
+            _ => (),
+        }
+
+        if !c.is_whitespace() {
+            after_annotation_name = false;
+        }
+    }
+
+    blocks
+}
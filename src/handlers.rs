@@ -2,63 +2,138 @@ use aidl_parser::symbol::Symbol;
 use aidl_parser::traverse::SymbolFilter;
 use aidl_parser::{ast, traverse};
 use anyhow::Result;
-
+use std::collections::HashMap;
+
+use crate::call_hierarchy;
+use crate::code_actions;
+use crate::code_lens;
+use crate::config;
+use crate::diagnostics;
+use crate::document_highlight;
+use crate::document_link;
+use crate::error::LspError;
+use crate::folding_range;
+use crate::hover;
 use crate::indexing;
+use crate::inlay_hints;
+use crate::log;
+use crate::lsp_ext;
+use crate::metrics;
+use crate::selection_range;
+use crate::signature_help;
 use crate::state::{GlobalState, IndexingState};
+use crate::type_hierarchy;
 use crate::utils;
+use crate::workspace_symbol;
 
 pub fn handle_initialized(
-    _global_state: &mut GlobalState,
+    global_state: &mut GlobalState,
     _params: lsp_types::InitializedParams,
 ) -> Result<()> {
+    // Settings of the client
+    if global_state.configuration_support {
+        request_configuration(global_state);
+    }
+
+    // Changes of the project configuration file
+    if global_state.watched_files_registration_support {
+        let options = lsp_types::DidChangeWatchedFilesRegistrationOptions {
+            watchers: Vec::from([lsp_types::FileSystemWatcher {
+                glob_pattern: format!("**/{}", config::PROJECT_CONFIG_FILE),
+                kind: None,
+            }]),
+        };
+
+        global_state.send_request::<lsp_types::request::RegisterCapability>(
+            lsp_types::RegistrationParams {
+                registrations: Vec::from([lsp_types::Registration {
+                    id: String::from("aidl-lsp-project-config"),
+                    method: String::from("workspace/didChangeWatchedFiles"),
+                    register_options: Some(serde_json::to_value(options)?),
+                }]),
+            },
+            |_, _| Ok(()),
+        );
+    }
+
     Ok(())
 }
 
-pub fn handle_workspace_symbol(
+pub fn handle_did_change_configuration(
     global_state: &mut GlobalState,
-    params: lsp_types::WorkspaceSymbolParams,
-) -> Result<Option<Vec<lsp_types::SymbolInformation>>> {
-    if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!("Cannot handle workspace symbol request: workspace has not been indexed!");
+    params: lsp_types::DidChangeConfigurationParams,
+) -> Result<()> {
+    // The settings of the notification are not standardized: pull them if possible
+    if global_state.configuration_support {
+        request_configuration(global_state);
+        return Ok(());
     }
 
-    let show_all_symbols = params.query.contains('#');
-    let name_filter: String = params
-        .query
-        .chars()
-        .filter(|&c| c != '#' && c != '*')
-        .collect();
-
-    let symbol_filter = if show_all_symbols {
-        SymbolFilter::All
-    } else {
-        SymbolFilter::ItemsOnly
+    let settings = match params.settings.get(config::CLIENT_CONFIG_SECTION) {
+        Some(settings) => settings.clone(),
+        None => params.settings,
     };
+    global_state.config_sources.client_settings = Some(settings);
 
-    // Collect all symbols by iterating over files and walking into symbols
-    let mut symbols = Vec::new();
-    global_state
-        .file_results
+    indexing::apply_config(global_state)
+}
+
+pub fn handle_did_change_watched_files(
+    global_state: &mut GlobalState,
+    params: lsp_types::DidChangeWatchedFilesParams,
+) -> Result<()> {
+    let project_file = global_state
+        .root_path
+        .as_ref()
+        .map(|root_path| root_path.join(config::PROJECT_CONFIG_FILE));
+
+    let project_file_changed = params
+        .changes
         .iter()
-        .filter_map(|(path, fr)| fr.ast.as_ref().map(|fr| (path, fr)))
-        .filter_map(|(path, ast)| utils::path_to_uri(path).ok().map(|uri| (uri, ast)))
-        .for_each(|(uri, ast)| {
-            traverse::walk_symbols(ast, symbol_filter, |symbol| {
-                // Filter by name
-                match symbol.get_name() {
-                    Some(n) if n.to_lowercase().contains(&name_filter) => (),
-                    _ => return,
-                };
-
-                // Convert to LSP symbol and add it to the list
-                if let Some(symbol) = utils::to_lsp_symbol_info(&symbol, uri.clone()) {
-                    symbols.push(symbol);
-                }
-            });
-        });
+        .any(|change| change.uri.to_file_path().ok() == project_file);
+    if project_file_changed {
+        indexing::reload_project_config(global_state)?;
+    }
+
+    Ok(())
+}
+
+// Pull the settings of the client (`workspace/configuration`)
+fn request_configuration(global_state: &mut GlobalState) {
+    global_state.send_request::<lsp_types::request::WorkspaceConfiguration>(
+        lsp_types::ConfigurationParams {
+            items: Vec::from([lsp_types::ConfigurationItem {
+                scope_uri: None,
+                section: Some(String::from(config::CLIENT_CONFIG_SECTION)),
+            }]),
+        },
+        handle_configuration_response,
+    );
+}
+
+fn handle_configuration_response(
+    global_state: &mut GlobalState,
+    result: serde_json::Value,
+) -> Result<()> {
+    let mut settings: Vec<serde_json::Value> = serde_json::from_value(result)?;
+    global_state.config_sources.client_settings = settings.pop().filter(|s| !s.is_null());
+
+    indexing::apply_config(global_state)
+}
+
+pub fn handle_workspace_symbol(
+    global_state: &mut GlobalState,
+    params: lsp_types::WorkspaceSymbolParams,
+) -> Result<Option<Vec<lsp_types::SymbolInformation>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "workspace symbol request",
+        )
+        .into());
+    }
 
-    // Sort symbols by name
-    symbols.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    let symbols = workspace_symbol::search(global_state, &params.query);
 
     Ok(Some(symbols))
 }
@@ -68,79 +143,28 @@ pub fn handle_document_symbol(
     params: lsp_types::DocumentSymbolParams,
 ) -> Result<Option<lsp_types::DocumentSymbolResponse>> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!("Cannot handle document symbol request: workspace has not been indexed!");
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "document symbol request").into(),
+        );
     }
 
-    let path = utils::uri_to_path(&params.text_document.uri)?;
-    let file_results = utils::get_file_results(global_state, &path)?;
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
 
     let ast = match &file_results.ast {
         Some(f) => f,
         None => return Ok(Some(lsp_types::DocumentSymbolResponse::Nested(Vec::new()))),
     };
 
-    // Collect all symbols by walking into symbols
-    let mut root_symbol: Option<lsp_types::DocumentSymbol> = None;
-
-    enum SymbolDef {
-        TopLevel,
-        Child,
-        None,
-    }
-
-    traverse::walk_symbols(ast, SymbolFilter::ItemsAndItemElements, |symbol| {
-        // Convert to LSP symbol and add it to the tree
+    // Build the tree of symbols: each symbol is a child of the symbol containing it
+    // (e.g. methods in interfaces, arguments in methods)
+    let mut symbols = Vec::new();
+    traverse::walk_symbols(ast, SymbolFilter::All, |symbol| {
         if let Some(doc_symbol) = utils::to_lsp_doc_symbol(&symbol) {
-            let symbol_def = match symbol {
-                Symbol::Package(..) => SymbolDef::TopLevel,
-                Symbol::Import(..) => SymbolDef::TopLevel,
-                Symbol::Interface(..) => SymbolDef::TopLevel,
-                Symbol::Parcelable(..) => SymbolDef::TopLevel,
-                Symbol::Enum(..) => SymbolDef::TopLevel,
-                Symbol::Method(..) => SymbolDef::Child,
-                Symbol::Arg(..) => SymbolDef::None,
-                Symbol::Const(..) => SymbolDef::Child,
-                Symbol::Field(..) => SymbolDef::Child,
-                Symbol::EnumElement(..) => SymbolDef::Child,
-                Symbol::Type(..) => SymbolDef::None,
-            };
-
-            match symbol_def {
-                SymbolDef::TopLevel => {
-                    if let Some(root_symbol) = &root_symbol {
-                        tracing::warn!(
-                            "Multiple root symbols found: {} and {}",
-                            root_symbol.name,
-                            doc_symbol.name
-                        );
-                    } else {
-                        // Set as root symbol
-                        root_symbol = Some(doc_symbol);
-                    }
-                }
-                SymbolDef::Child => {
-                    if let Some(parent) = &mut root_symbol {
-                        if let Some(children) = parent.children.as_mut() {
-                            // Append to the list of children
-                            children.push(doc_symbol);
-                        } else {
-                            // First child
-                            parent.children = Some(Vec::from([doc_symbol]));
-                        }
-                    } else {
-                        tracing::warn!("No parent symbol found for doc symbol {}", doc_symbol.name);
-                    }
-                }
-                SymbolDef::None => (),
-            };
+            utils::insert_doc_symbol(&mut symbols, doc_symbol);
         }
     });
 
-    let symbols = match root_symbol {
-        Some(rs) => Vec::from([rs]),
-        None => Vec::new(),
-    };
-
     tracing::info!("Document symbols = {:?}", symbols);
     Ok(Some(lsp_types::DocumentSymbolResponse::Nested(symbols)))
 }
@@ -150,55 +174,50 @@ pub fn handle_hover(
     params: lsp_types::HoverParams,
 ) -> Result<Option<lsp_types::Hover>> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!("Cannot handle hover request: workspace has not been indexed!");
+        return Err(LspError::not_indexed(&global_state.indexing_state, "hover request").into());
     }
 
-    let path = utils::uri_to_path(&params.text_document_position_params.text_document.uri)?;
-    let file_results = utils::get_file_results(global_state, &path)?;
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
 
-    let ast = match &file_results.ast {
-        Some(f) => f,
-        None => return Ok(None),
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
     };
 
-    let target_line_col = utils::from_lsp_position(&params.text_document_position_params.position);
-    let hover =
-        traverse::find_symbol_at_line_col(ast, traverse::SymbolFilter::All, target_line_col).map(
-            |smb| {
-                let signature: Option<String> = if let Symbol::Type(ast::Type {
-                    kind: ast::TypeKind::Resolved(qualified_name, Some(item_kind)),
-                    ..
-                }) = smb
-                {
-                    let name = qualified_name.split('.').last().unwrap_or("");
-                    Some(match item_kind {
-                        ast::ItemKind::Interface => format!("interface {}", name),
-                        ast::ItemKind::Parcelable => format!("parcelable {}", name),
-                        ast::ItemKind::Enum => format!("enum {}", name),
-                    })
-                } else {
-                    None
-                };
-
-                let signature = signature.unwrap_or_else(|| smb.get_signature());
-
-                let markdown = lsp_types::MarkupContent {
-                    kind: lsp_types::MarkupKind::Markdown,
-                    value: [
-                        &smb.get_qualified_name().unwrap_or_default(),
-                        "```aidl",
-                        &signature,
-                        "```",
-                    ]
-                    .join("\n"),
-                };
-
-                lsp_types::Hover {
-                    contents: lsp_types::HoverContents::Markup(markdown),
-                    range: Some(utils::to_lsp_range(smb.get_range())),
-                }
-            },
-        );
+    let position = &params.text_document_position_params.position;
+    let target_line_col = utils::from_lsp_position(position);
+    let to_hover = |markdown, range| lsp_types::Hover {
+        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: markdown,
+        }),
+        range: Some(range),
+    };
+
+    if let Some(smb) =
+        traverse::find_symbol_at_line_col(ast, traverse::SymbolFilter::All, target_line_col)
+    {
+        let markdown = hover::get_hover_markdown(global_state, ast, content, &smb);
+        return Ok(Some(to_hover(
+            markdown,
+            utils::to_lsp_range(smb.get_range()),
+        )));
+    }
+
+    // Constants which are not part of the AST (values with expressions)
+    let encoding = global_state.position_encoding;
+    let hover = utils::to_offset(content, position, encoding)
+        .and_then(|offset| {
+            hover::get_declaration_hover_markdown(global_state, ast, content, offset)
+        })
+        .map(|(markdown, range)| {
+            let range = lsp_types::Range {
+                start: utils::offset_to_lsp_position(content, range.start, encoding),
+                end: utils::offset_to_lsp_position(content, range.end, encoding),
+            };
+            to_hover(markdown, range)
+        });
 
     Ok(hover)
 }
@@ -208,11 +227,13 @@ pub fn handle_goto_definition(
     params: lsp_types::GotoDefinitionParams,
 ) -> Result<Option<lsp_types::GotoDefinitionResponse>> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!("Cannot handle goto definition request: workspace has not been indexed!");
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "goto definition request").into(),
+        );
     }
 
-    let path = utils::uri_to_path(&params.text_document_position_params.text_document.uri)?;
-    let file_results = utils::get_file_results(global_state, &path)?;
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
 
     let file = match &file_results.ast {
         Some(f) => f,
@@ -221,52 +242,519 @@ pub fn handle_goto_definition(
 
     let pos = utils::from_lsp_position(&params.text_document_position_params.position);
 
-    let link = traverse::find_symbol_at_line_col(file, SymbolFilter::All, pos)
-        .map(|symbol| {
-            let key_and_range = match symbol {
-                Symbol::Import(i) => Some((i.get_qualified_name(), &i.symbol_range)),
-                Symbol::Type(
-                    t
-                    @
-                    ast::Type {
-                        kind: ast::TypeKind::Resolved(qualified_name, _),
-                        ..
-                    },
-                ) => Some((qualified_name.clone(), &t.symbol_range)),
-                _ => None,
-            };
+    let link = traverse::find_symbol_at_line_col(file, SymbolFilter::All, pos).and_then(|symbol| {
+        let key_and_range = match symbol {
+            Symbol::Import(i) => Some((i.get_qualified_name(), &i.symbol_range)),
+            Symbol::Type(
+                t @ ast::Type {
+                    kind: ast::TypeKind::Resolved(qualified_name, _),
+                    ..
+                },
+            ) => Some((qualified_name.clone(), &t.symbol_range)),
+            _ => None,
+        };
 
-            key_and_range
-                .map(|(key, range)| utils::get_target_link(global_state, range, &key))
-                .flatten()
-        })
-        .flatten();
+        key_and_range.and_then(|(key, range)| utils::get_target_link(global_state, range, &key))
+    });
 
     let response = link.map(|l| lsp_types::GotoDefinitionResponse::Link(Vec::from([l])));
 
     Ok(response)
 }
 
+pub fn handle_document_highlight(
+    global_state: &mut GlobalState,
+    params: lsp_types::DocumentHighlightParams,
+) -> Result<Option<Vec<lsp_types::DocumentHighlight>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "document highlight request",
+        )
+        .into());
+    }
+
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(document_highlight::get_document_highlights(
+        ast,
+        content,
+        &params.text_document_position_params.position,
+        global_state.position_encoding,
+    )))
+}
+
+pub fn handle_folding_range(
+    global_state: &mut GlobalState,
+    params: lsp_types::FoldingRangeParams,
+) -> Result<Option<Vec<lsp_types::FoldingRange>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "folding range request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(folding_range::get_folding_ranges(ast, content)))
+}
+
+pub fn handle_selection_range(
+    global_state: &mut GlobalState,
+    params: lsp_types::SelectionRangeParams,
+) -> Result<Option<Vec<lsp_types::SelectionRange>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "selection range request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
+    };
+
+    let selection_ranges = params
+        .positions
+        .iter()
+        .map(|position| {
+            selection_range::get_selection_range(
+                ast,
+                content,
+                position,
+                global_state.position_encoding,
+            )
+        })
+        .collect();
+
+    Ok(Some(selection_ranges))
+}
+
+pub fn handle_document_link(
+    global_state: &mut GlobalState,
+    params: lsp_types::DocumentLinkParams,
+) -> Result<Option<Vec<lsp_types::DocumentLink>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "document link request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(document_link::get_document_links(
+        global_state,
+        ast,
+        content,
+    )))
+}
+
+pub fn handle_prepare_type_hierarchy(
+    global_state: &mut GlobalState,
+    params: lsp_ext::TypeHierarchyPrepareParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "type hierarchy request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let item = file_results.ast.as_ref().and_then(|ast| {
+        type_hierarchy::prepare(
+            global_state,
+            ast,
+            &params.text_document_position_params.position,
+        )
+    });
+
+    Ok(item.map(|i| Vec::from([i])))
+}
+
+pub fn handle_type_hierarchy_supertypes(
+    global_state: &mut GlobalState,
+    params: lsp_ext::TypeHierarchyParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "supertypes request").into(),
+        );
+    }
+
+    Ok(Some(type_hierarchy::get_supertypes(
+        global_state,
+        &params.item,
+    )))
+}
+
+pub fn handle_type_hierarchy_subtypes(
+    global_state: &mut GlobalState,
+    params: lsp_ext::TypeHierarchyParams,
+) -> Result<Option<Vec<lsp_ext::TypeHierarchyItem>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(LspError::not_indexed(&global_state.indexing_state, "subtypes request").into());
+    }
+
+    Ok(Some(type_hierarchy::get_subtypes(
+        global_state,
+        &params.item,
+    )))
+}
+
+pub fn handle_prepare_call_hierarchy(
+    global_state: &mut GlobalState,
+    params: lsp_types::CallHierarchyPrepareParams,
+) -> Result<Option<Vec<lsp_types::CallHierarchyItem>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "call hierarchy request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let item = file_results.ast.as_ref().and_then(|ast| {
+        call_hierarchy::prepare(
+            global_state,
+            ast,
+            &params.text_document_position_params.position,
+        )
+    });
+
+    Ok(item.map(|i| Vec::from([i])))
+}
+
+pub fn handle_call_hierarchy_incoming_calls(
+    global_state: &mut GlobalState,
+    params: lsp_types::CallHierarchyIncomingCallsParams,
+) -> Result<Option<Vec<lsp_types::CallHierarchyIncomingCall>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "incoming calls request").into(),
+        );
+    }
+
+    Ok(Some(call_hierarchy::get_incoming_calls(
+        global_state,
+        &params.item,
+    )))
+}
+
+pub fn handle_call_hierarchy_outgoing_calls(
+    global_state: &mut GlobalState,
+    params: lsp_types::CallHierarchyOutgoingCallsParams,
+) -> Result<Option<Vec<lsp_types::CallHierarchyOutgoingCall>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "outgoing calls request").into(),
+        );
+    }
+
+    Ok(Some(call_hierarchy::get_outgoing_calls(
+        global_state,
+        &params.item,
+    )))
+}
+
+pub fn handle_document_diagnostic(
+    global_state: &mut GlobalState,
+    params: lsp_ext::DocumentDiagnosticParams,
+) -> Result<lsp_ext::DocumentDiagnosticReport> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "diagnostic request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+
+    diagnostics::get_document_report(global_state, &uri, params.previous_result_id.as_deref())
+}
+
+pub fn handle_workspace_diagnostic(
+    global_state: &mut GlobalState,
+    id: lsp_server::RequestId,
+    params: lsp_ext::WorkspaceDiagnosticParams,
+) -> Result<Option<lsp_ext::WorkspaceDiagnosticReport>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "workspace diagnostic request",
+        )
+        .into());
+    }
+
+    let previous_result_ids: HashMap<lsp_types::Url, String> = params
+        .previous_result_ids
+        .into_iter()
+        .map(|r| (r.uri, r.value))
+        .collect();
+
+    let report = diagnostics::get_workspace_report(global_state, &previous_result_ids)?;
+    if !report.items.is_empty() {
+        return Ok(Some(report));
+    }
+
+    // Nothing changed: wait for a change before answering (the client would send the
+    // same request again right away), the previous pending request becomes obsolete
+    if let Some((previous_id, _)) = global_state.pending_workspace_diagnostics.take() {
+        let response = lsp_server::Response::new_ok(previous_id, report);
+        global_state
+            .sender
+            .send(lsp_server::Message::Response(response))?;
+    }
+    global_state.pending_workspace_diagnostics = Some((id, previous_result_ids));
+
+    Ok(None)
+}
+
+pub fn handle_code_action(
+    global_state: &mut GlobalState,
+    params: lsp_types::CodeActionParams,
+) -> Result<Option<lsp_types::CodeActionResponse>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "code action request").into(),
+        );
+    }
+
+    // Kinds are hierarchical (e.g. `source` includes `source.organizeImports` but not
+    // `sourceX`)
+    let is_kind_requested = |kind: lsp_types::CodeActionKind| match &params.context.only {
+        Some(only) => only
+            .iter()
+            .any(|o| kind == *o || kind.as_str().starts_with(&format!("{}.", o.as_str()))),
+        None => true,
+    };
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let ast = match &file_results.ast {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+
+    let mut actions = Vec::new();
+
+    if is_kind_requested(lsp_types::CodeActionKind::QUICKFIX) {
+        actions.extend(code_actions::get_quick_fixes(
+            global_state,
+            &uri,
+            ast,
+            &params.range,
+            &params.context.diagnostics,
+        ));
+    }
+
+    if is_kind_requested(lsp_types::CodeActionKind::SOURCE_ORGANIZE_IMPORTS) {
+        actions.extend(code_actions::get_organize_imports_action(
+            global_state,
+            &uri,
+            ast,
+        ));
+    }
+
+    Ok(Some(actions))
+}
+
+pub fn handle_execute_command(
+    global_state: &mut GlobalState,
+    params: lsp_types::ExecuteCommandParams,
+) -> Result<Option<serde_json::Value>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "execute command request").into(),
+        );
+    }
+
+    match params.command.as_str() {
+        code_actions::ORGANIZE_IMPORTS_COMMAND => {
+            let uri: lsp_types::Url = match params.arguments.first() {
+                Some(arg) => serde_json::from_value(arg.clone())?,
+                None => {
+                    return Err(LspError::new(
+                        lsp_server::ErrorCode::InvalidParams,
+                        format!("Missing URI argument for {}", params.command),
+                    )
+                    .into())
+                }
+            };
+
+            let uri = utils::normalize_uri(&uri);
+            let file_results = utils::get_file_results(global_state, &uri)?;
+
+            let edit = file_results
+                .ast
+                .as_ref()
+                .and_then(|ast| code_actions::get_organize_imports_edit(global_state, &uri, ast));
+
+            // The edit is applied by the client (workspace/applyEdit), not returned
+            if let Some(edit) = edit {
+                global_state.send_request::<lsp_types::request::ApplyWorkspaceEdit>(
+                    lsp_types::ApplyWorkspaceEditParams {
+                        label: Some(String::from("Organize imports")),
+                        edit,
+                    },
+                    handle_apply_edit_response,
+                );
+            }
+
+            Ok(None)
+        }
+        _ => Err(LspError::new(
+            lsp_server::ErrorCode::InvalidParams,
+            format!("Unknown command: {}", params.command),
+        )
+        .into()),
+    }
+}
+
+fn handle_apply_edit_response(
+    global_state: &mut GlobalState,
+    result: serde_json::Value,
+) -> Result<()> {
+    let response: lsp_types::ApplyWorkspaceEditResponse = serde_json::from_value(result)?;
+    if !response.applied {
+        let reason = response
+            .failure_reason
+            .unwrap_or_else(|| String::from("edit rejected by the client"));
+        tracing::warn!("Edit not applied: {}", reason);
+        global_state.show_message(
+            lsp_types::MessageType::WARNING,
+            format!("Could not organize imports: {}", reason),
+        );
+    }
+
+    Ok(())
+}
+
+pub fn handle_inlay_hint(
+    global_state: &mut GlobalState,
+    params: lsp_ext::InlayHintParams,
+) -> Result<Option<Vec<lsp_ext::InlayHint>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "inlay hint request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let (ast, content) = match (&file_results.ast, global_state.file_contents.get(&uri)) {
+        (Some(ast), Some(content)) => (ast, content),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(inlay_hints::get_inlay_hints(
+        ast,
+        content,
+        &params.range,
+    )))
+}
+
+pub fn handle_code_lens(
+    global_state: &mut GlobalState,
+    params: lsp_types::CodeLensParams,
+) -> Result<Option<Vec<lsp_types::CodeLens>>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "code lens request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let lenses = file_results
+        .ast
+        .as_ref()
+        .map(|ast| code_lens::get_code_lenses(&uri, ast));
+
+    Ok(lenses)
+}
+
+pub fn handle_signature_help(
+    global_state: &mut GlobalState,
+    params: lsp_types::SignatureHelpParams,
+) -> Result<Option<lsp_types::SignatureHelp>> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(
+            LspError::not_indexed(&global_state.indexing_state, "signature help request").into(),
+        );
+    }
+
+    let uri = utils::normalize_uri(&params.text_document_position_params.text_document.uri);
+    let file_results = utils::get_file_results(global_state, &uri)?;
+
+    let content = match global_state.file_contents.get(&uri) {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+
+    Ok(signature_help::get_signature_help(
+        global_state,
+        content,
+        file_results.ast.as_ref(),
+        &params.text_document_position_params.position,
+    ))
+}
+
 pub fn handle_did_change_text_document(
     global_state: &mut GlobalState,
     params: lsp_types::DidChangeTextDocumentParams,
 ) -> Result<()> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!(
-            "Cannot handle did change text document notification: workspace has not been indexed!"
-        );
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "did change text document notification",
+        )
+        .into());
     }
 
     if params.content_changes.len() != 1 {
-        anyhow::bail!(
-            "Unsupported content change with length {}",
-            params.content_changes.len()
-        );
+        return Err(LspError::new(
+            lsp_server::ErrorCode::InvalidParams,
+            format!(
+                "Unsupported content change with length {}",
+                params.content_changes.len()
+            ),
+        )
+        .into());
     }
 
     let content_change = &params.content_changes[0];
     if content_change.range.is_some() {
-        anyhow::bail!("Unexpected range in content change: only full change can be provided!");
+        return Err(LspError::new(
+            lsp_server::ErrorCode::InvalidParams,
+            "Unexpected range in content change: only full change can be provided!",
+        )
+        .into());
     }
 
     indexing::update_content(
@@ -280,28 +768,100 @@ pub fn handle_did_change_text_document(
 
 pub fn handle_did_open_text_document(
     global_state: &mut GlobalState,
-    _params: lsp_types::DidOpenTextDocumentParams,
+    params: lsp_types::DidOpenTextDocumentParams,
 ) -> Result<()> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!(
-            "Cannot handle did open text document notification: workspace has not been indexed!"
-        );
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "did open text document notification",
+        )
+        .into());
+    }
+
+    // Files of the workspace are already indexed (unless modified in the meantime),
+    // virtual documents are only known once opened
+    let uri = utils::normalize_uri(&params.text_document.uri);
+    global_state.open_documents.insert(uri.clone());
+    if global_state.file_contents.get(&uri) != Some(&params.text_document.text) {
+        indexing::update_content(global_state, &uri, &params.text_document.text)?;
     }
 
     Ok(())
 }
 
+pub fn handle_did_close_text_document(
+    global_state: &mut GlobalState,
+    params: lsp_types::DidCloseTextDocumentParams,
+) -> Result<()> {
+    if global_state.indexing_state != IndexingState::Indexed {
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "did close text document notification",
+        )
+        .into());
+    }
+
+    indexing::close_document(global_state, &params.text_document.uri)
+}
+
 pub fn handle_did_save_text_document(
     global_state: &mut GlobalState,
     params: lsp_types::DidSaveTextDocumentParams,
 ) -> Result<()> {
     if global_state.indexing_state != IndexingState::Indexed {
-        anyhow::bail!(
-            "Cannot handle did save text document notification: workspace has not been indexed!"
-        );
+        return Err(LspError::not_indexed(
+            &global_state.indexing_state,
+            "did save text document notification",
+        )
+        .into());
+    }
+
+    // Virtual documents are not saved on disk
+    if utils::is_virtual_document(&params.text_document.uri) {
+        return Ok(());
     }
 
     indexing::update_file(global_state, &params.text_document.uri)?;
 
     Ok(())
 }
+
+pub fn handle_status(global_state: &mut GlobalState, _params: ()) -> Result<lsp_ext::ServerStatus> {
+    Ok(metrics::get_status(global_state))
+}
+
+pub fn handle_set_trace(
+    _global_state: &mut GlobalState,
+    params: lsp_ext::SetTraceParams,
+) -> Result<()> {
+    log::set_trace(params.value);
+    Ok(())
+}
+
+pub fn handle_cancel_request(
+    global_state: &mut GlobalState,
+    params: lsp_types::CancelParams,
+) -> Result<()> {
+    let id: lsp_server::RequestId = match params.id {
+        lsp_types::NumberOrString::Number(id) => id.into(),
+        lsp_types::NumberOrString::String(id) => id.into(),
+    };
+
+    // Only the deferred requests can still be pending (the other ones are answered
+    // right away)
+    let is_pending = matches!(
+        &global_state.pending_workspace_diagnostics,
+        Some((pending_id, _)) if *pending_id == id
+    );
+    if is_pending {
+        global_state.pending_workspace_diagnostics = None;
+
+        let error = LspError::cancelled();
+        let response = lsp_server::Response::new_err(id, error.code, error.message);
+        global_state
+            .sender
+            .send(lsp_server::Message::Response(response))?;
+    }
+
+    Ok(())
+}
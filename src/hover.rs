@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use aidl_parser::ast;
+use aidl_parser::symbol::Symbol;
+
+use crate::backends;
+use crate::state::GlobalState;
+use crate::utils;
+use crate::values;
+
+/// Markdown content of the hover for a given symbol of a document
+///
+/// For type references (and imports), the hover shows the referenced item.
+pub fn get_hover_markdown(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    content: &str,
+    symbol: &Symbol,
+) -> String {
+    let target_key = match symbol {
+        Symbol::Type(ast::Type {
+            kind: ast::TypeKind::Resolved(key, Some(_)),
+            ..
+        }) => Some(key.clone()),
+        Symbol::Import(i) => Some(i.get_qualified_name()),
+        _ => None,
+    };
+
+    let target = target_key.and_then(|key| {
+        let ast = utils::get_item_ast(global_state, &key)?;
+        let content = global_state
+            .file_contents
+            .get(global_state.items_by_key.get(&key)?)?;
+        Some((ast, content))
+    });
+
+    match target {
+        Some((ast, content)) => {
+            get_symbol_markdown(global_state, ast, content, &utils::get_item_symbol(ast))
+        }
+        None => get_symbol_markdown(global_state, ast, content, symbol),
+    }
+}
+
+/// Markdown content of the hover for a constant at the given offset which is not part
+/// of the AST, i.e. whose value is an expression (e.g. `const int B = 1 << A;`), or for
+/// a constant referenced by such a value, with the range of the name (offsets)
+pub fn get_declaration_hover_markdown(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    content: &str,
+    offset: usize,
+) -> Option<(String, std::ops::Range<usize>)> {
+    let interface = ast.item.as_interface()?;
+    let declarations = values::get_declarations(&ast.item, content);
+
+    let contains = |t: &values::Token| t.offset <= offset && offset <= t.offset + t.text.len();
+    let name = declarations.iter().find_map(|d| {
+        if contains(&d.name) {
+            return Some(d.name);
+        }
+
+        d.value
+            .iter()
+            .flatten()
+            .filter_map(|t| values::get_referenced_name(t, &interface.name))
+            .find(contains)
+    })?;
+    let range = name.offset..name.offset + name.text.len();
+
+    // Constant of the AST
+    let ast_const = interface.elements.iter().find_map(|el| match el {
+        ast::InterfaceElement::Const(c) if c.name == name.text => Some(c),
+        _ => None,
+    });
+    if let Some(c) = ast_const {
+        let symbol = Symbol::Const(c, interface);
+        return Some((
+            get_symbol_markdown(global_state, ast, content, &symbol),
+            range,
+        ));
+    }
+
+    let declaration = declarations.iter().find(|d| d.name.text == name.text)?;
+    let tokens = declaration.value.as_ref()?;
+    let value_str = match get_const_values(global_state, ast, interface, content).get(name.text) {
+        Some(v) => get_int_value_str(*v),
+        None => {
+            let (first, last) = (tokens.first()?, tokens.last()?);
+            format!(
+                "`{}`",
+                &content[first.offset..last.offset + last.text.len()]
+            )
+        }
+    };
+
+    let signature = match declaration.type_name {
+        Some(t) => format!("const {} {}", t.text, name.text),
+        None => format!("const {}", name.text),
+    };
+
+    let markdown = [
+        [
+            format!("{}::{}", interface.name, name.text).as_str(),
+            "```aidl",
+            &signature,
+            "```",
+        ]
+        .join("\n"),
+        format!("Value: {}", value_str),
+    ]
+    .join("\n\n---\n\n");
+
+    Some((markdown, range))
+}
+
+fn get_symbol_markdown(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    content: &str,
+    symbol: &Symbol,
+) -> String {
+    let mut sections = Vec::new();
+
+    // Qualified name + annotations and signature
+    let mut signature_lines: Vec<String> = get_annotations(symbol)
+        .iter()
+        .map(utils::get_annotation_str)
+        .collect();
+    signature_lines.push(symbol.get_signature());
+
+    sections.push(
+        [
+            &utils::get_qualified_name(symbol).unwrap_or_default(),
+            "```aidl",
+            &signature_lines.join("\n"),
+            "```",
+        ]
+        .join("\n"),
+    );
+
+    // Values (constants, enum elements) and backing type (enums)
+    let values = get_values_markdown(global_state, ast, content, symbol);
+    if !values.is_empty() {
+        sections.push(values.join("\n\n"));
+    }
+
+    // Documentation
+    if let Some(doc) = get_doc(symbol) {
+        sections.push(doc_to_markdown(doc));
+    }
+
+    // Signatures generated by the backends (if configured)
+    let backend_signatures: Vec<String> = global_state
+        .config
+        .hover_backends
+        .iter()
+        .filter_map(|backend| {
+            backends::get_signature(global_state, *backend, symbol).map(|signature| {
+                format!(
+                    "{}:\n```{}\n{}\n```",
+                    backend.get_display_name(),
+                    backend.get_language(),
+                    signature
+                )
+            })
+        })
+        .collect();
+    if !backend_signatures.is_empty() {
+        sections.push(backend_signatures.join("\n"));
+    }
+
+    sections.join("\n\n---\n\n")
+}
+
+fn get_values_markdown(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    content: &str,
+    symbol: &Symbol,
+) -> Vec<String> {
+    match symbol {
+        Symbol::Const(c, interface) => {
+            let value_str = match get_const_values(global_state, ast, interface, content)
+                .get(c.name.as_str())
+            {
+                Some(v) => get_int_value_str(*v),
+                None => format!("`{}`", c.value),
+            };
+            Vec::from([format!("Value: {}", value_str)])
+        }
+        Symbol::Enum(e, _) => Vec::from([format!(
+            "Backing type: `{}`",
+            utils::get_enum_backing_type(e)
+        )]),
+        Symbol::EnumElement(el, e) => {
+            let value = e
+                .elements
+                .iter()
+                .position(|other| std::ptr::eq(other, *el))
+                .and_then(|index| utils::get_enum_element_values(e, content)[index]);
+
+            let value_str = match (value, &el.value) {
+                (Some(v), None) => format!("`{}` (implicit)", v),
+                (Some(v), Some(_)) => get_int_value_str(v),
+                (None, Some(explicit)) => format!("`{}`", explicit),
+                (None, None) => String::from("unknown"),
+            };
+
+            Vec::from([
+                format!("Value: {}", value_str),
+                format!("Backing type: `{}`", utils::get_enum_backing_type(e)),
+            ])
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Values of the integer constants of an interface, the references to the constants of
+// other interfaces (e.g. `IBar.FOO`) being resolved in the workspace
+fn get_const_values<'a>(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    interface: &ast::Interface,
+    content: &'a str,
+) -> HashMap<&'a str, i64> {
+    values::evaluate_constants(interface, content, &|t: &values::Token| {
+        let (item_name, const_name) = t.text.rsplit_once('.')?;
+        let key = utils::resolve_item_key(global_state, ast, item_name)?;
+        let target_interface = utils::get_item_ast(global_state, &key)?
+            .item
+            .as_interface()?;
+        let target_content = global_state
+            .file_contents
+            .get(global_state.items_by_key.get(&key)?)?;
+
+        // The references of the other interface are not followed (possible cycles)
+        values::evaluate_constants(target_interface, target_content, &|_| None)
+            .get(const_name)
+            .copied()
+    })
+}
+
+// Integer value, with its hexadecimal representation
+fn get_int_value_str(value: i64) -> String {
+    if value > 9 {
+        format!("`{}` (`{:#x}`)", value, value)
+    } else {
+        format!("`{}`", value)
+    }
+}
+
+fn get_annotations<'a>(symbol: &Symbol<'a>) -> &'a [ast::Annotation] {
+    match symbol {
+        Symbol::Interface(i, _) => &i.annotations,
+        Symbol::Parcelable(p, _) => &p.annotations,
+        Symbol::Enum(e, _) => &e.annotations,
+        Symbol::Method(m, _) => &m.annotations,
+        Symbol::Arg(a, _) => &a.annotations,
+        Symbol::Const(c, _) => &c.annotations,
+        Symbol::Field(f, _) => &f.annotations,
+        _ => &[],
+    }
+}
+
+fn get_doc<'a>(symbol: &Symbol<'a>) -> Option<&'a str> {
+    match symbol {
+        Symbol::Interface(i, _) => i.doc.as_deref(),
+        Symbol::Parcelable(p, _) => p.doc.as_deref(),
+        Symbol::Enum(e, _) => e.doc.as_deref(),
+        Symbol::Method(m, _) => m.doc.as_deref(),
+        Symbol::Arg(a, _) => a.doc.as_deref(),
+        Symbol::Const(c, _) => c.doc.as_deref(),
+        Symbol::Field(f, _) => f.doc.as_deref(),
+        Symbol::EnumElement(el, _) => el.doc.as_deref(),
+        _ => None,
+    }
+}
+
+/// Convert a Javadoc comment (as extracted by aidl_parser: one paragraph or tag per line)
+/// into Markdown
+pub fn doc_to_markdown(doc: &str) -> String {
+    let mut paragraphs = Vec::new();
+    let mut tags = Vec::new();
+
+    for line in doc.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let line = replace_inline_tags(line);
+
+        if let Some(tag_line) = line.strip_prefix('@') {
+            let (tag, rest) = tag_line.split_once(' ').unwrap_or((tag_line, ""));
+            let rest = rest.trim();
+
+            tags.push(match tag {
+                "param" => {
+                    let (name, description) = rest.split_once(' ').unwrap_or((rest, ""));
+                    format!("- *@param* `{}` — {}", name, description.trim())
+                }
+                _ => format!("- *@{}* {}", tag, rest).trim_end().to_owned(),
+            });
+        } else {
+            paragraphs.push(line);
+        }
+    }
+
+    if !tags.is_empty() {
+        paragraphs.push(tags.join("\n"));
+    }
+
+    paragraphs.join("\n\n")
+}
+
+// Replace `{@link Foo}` and `{@code foo}` by `Foo` and `foo` (as code)
+fn replace_inline_tags(line: &str) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find("{@") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let content = &rest[start + 2..end];
+        let content = content
+            .split_once(char::is_whitespace)
+            .map(|(_, c)| c.trim())
+            .unwrap_or_default();
+
+        result.push_str(&rest[..start]);
+        result.push_str(&format!("`{}`", content));
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{handlers, indexing, utils};
+
+    const IFOO: &str = "package a;\ninterface IFoo {\n    const int A = 0x10;\n    const int B = A << 2;\n    const long C = IBar.X - 1;\n}\n";
+    const IBAR: &str = "package a;\ninterface IBar {\n    const int X = 1 << 3;\n}\n";
+
+    // Hover at the first occurrence of the pattern in IFoo (and range of the hover)
+    fn hover(pattern: &str) -> Option<(String, lsp_types::Range)> {
+        let (mut global_state, _receiver) =
+            indexing::index_test_files(&[("a/IFoo.aidl", IFOO), ("a/IBar.aidl", IBAR)]);
+
+        let offset = IFOO.find(pattern).unwrap();
+        let params = lsp_types::HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: utils::path_to_uri(std::path::Path::new("/ws/a/IFoo.aidl")).unwrap(),
+                },
+                position: utils::offset_to_lsp_position(
+                    IFOO,
+                    offset,
+                    global_state.position_encoding,
+                ),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        handlers::handle_hover(&mut global_state, params)
+            .unwrap()
+            .map(|hover| match hover.contents {
+                lsp_types::HoverContents::Markup(markup) => (markup.value, hover.range.unwrap()),
+                contents => panic!("unexpected contents: {:?}", contents),
+            })
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> lsp_types::Range {
+        lsp_types::Range {
+            start: lsp_types::Position::new(line, start),
+            end: lsp_types::Position::new(line, end),
+        }
+    }
+
+    #[test]
+    fn literal_constant() {
+        let (markdown, _) = hover("A =").unwrap();
+        assert!(
+            markdown.starts_with("IFoo::A\n```aidl\nconst int A\n```"),
+            "{}",
+            markdown
+        );
+        assert!(markdown.contains("Value: `16` (`0x10`)"), "{}", markdown);
+    }
+
+    #[test]
+    fn expression_constants() {
+        // Not part of the AST: read from the content
+        let (markdown, hover_range) = hover("B =").unwrap();
+        assert_eq!(
+            markdown,
+            "IFoo::B\n```aidl\nconst int B\n```\n\n---\n\nValue: `64` (`0x40`)"
+        );
+        assert_eq!(hover_range, range(3, 14, 15));
+
+        // Referencing a constant of another interface which is also an expression
+        let (markdown, _) = hover("C =").unwrap();
+        assert!(markdown.contains("const long C"), "{}", markdown);
+        assert!(markdown.ends_with("Value: `7`"), "{}", markdown);
+    }
+
+    #[test]
+    fn reference_in_value() {
+        let (markdown, hover_range) = hover("A << 2").unwrap();
+        assert!(markdown.starts_with("IFoo::A\n"), "{}", markdown);
+        assert_eq!(hover_range, range(3, 18, 19));
+    }
+}
@@ -1,9 +1,12 @@
+use aidl_parser::{ast, Parser};
 use anyhow::{Context, Result};
-use lsp_types::notification::Notification;
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, fs::File, io::Read, time::Instant};
 use walkdir::WalkDir;
 
-use crate::{state::GlobalState, state::IndexingState, utils};
+use crate::{
+    checks, config, crash, diagnostics, error::LspError, excludes::Excludes, log, lsp_ext,
+    position_encoding, state::GlobalState, state::IndexingState, utils,
+};
 
 pub fn index(global_state: &mut GlobalState) -> Result<()> {
     if global_state.indexing_state == IndexingState::Indexing {
@@ -12,9 +15,23 @@ pub fn index(global_state: &mut GlobalState) -> Result<()> {
     }
 
     global_state.indexing_state = IndexingState::Indexing;
+    let _span = tracing::info_span!("index").entered();
+    let start = Instant::now();
 
-    match do_index(global_state) {
+    // A panic (e.g. of the parser) must not leave the state as "indexing"
+    let result = crash::catch_unwind(|| do_index(global_state))
+        .unwrap_or_else(|panic_message| Err(anyhow::anyhow!("panic: {}", panic_message)));
+
+    let duration = start.elapsed();
+    global_state.metrics.last_index_duration = Some(duration);
+
+    match result {
         Ok(()) => {
+            tracing::info!(
+                "Indexed {} files in {} ms",
+                global_state.file_results.len(),
+                duration.as_millis()
+            );
             global_state.indexing_state = IndexingState::Indexed;
         }
         Err(e) => {
@@ -32,9 +49,11 @@ fn do_index(global_state: &mut GlobalState) -> Result<()> {
         .as_ref()
         .context("No root path set")?;
 
+    let excludes = &global_state.excludes;
     let mut aidl_file_entries = WalkDir::new(path)
         .min_depth(1)
         .into_iter()
+        .filter_entry(|e| !excludes.is_excluded(path, e.path()))
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file())
         .filter(|e| {
@@ -45,6 +64,7 @@ fn do_index(global_state: &mut GlobalState) -> Result<()> {
                 .unwrap_or(false)
         });
 
+    let parse_span = tracing::info_span!("parse_files").entered();
     aidl_file_entries.try_for_each(|e| {
         // As std::fs::canonicalize() leads to some issues on Windows because it returns UNC
         // paths which are not properly handled, we use another version (dunce)
@@ -56,27 +76,22 @@ fn do_index(global_state: &mut GlobalState) -> Result<()> {
         file.read_to_string(&mut buffer)?;
 
         tracing::debug!("Parsing {:?}", path);
-        global_state.parser.add_content(path, &buffer);
+        let uri = utils::path_to_uri(&path)?;
+        global_state.parser.add_content(uri.clone(), &buffer);
+        global_state.file_contents.insert(uri, buffer);
 
         Ok(()) as Result<()>
     })?;
+    drop(parse_span);
 
-    global_state.file_results = global_state.parser.validate();
-    global_state.items_by_key = global_state
-        .file_results
-        .iter()
-        .filter_map(|(id, fr)| fr.ast.as_ref().map(|f| (f.get_key(), id.clone())))
-        .collect();
-
-    notify_diagnostics(global_state)?;
+    validate(global_state);
+    update_diagnostics(global_state)?;
 
     Ok(())
 }
 
 pub fn update_file(global_state: &mut GlobalState, uri: &lsp_types::Url) -> Result<()> {
-    let path = uri
-        .to_file_path()
-        .map_err(|_| anyhow::format_err!("Invalid root path (not a file path): {}", uri))?;
+    let path = uri.to_file_path().map_err(|_| LspError::invalid_uri(uri))?;
 
     let mut file = std::fs::File::open(path)?;
     let mut buffer = String::new();
@@ -92,97 +107,372 @@ pub fn update_content(
     uri: &lsp_types::Url,
     content: &str,
 ) -> Result<()> {
-    let parser = &mut global_state.parser;
+    let uri = utils::normalize_uri(uri);
+    global_state
+        .file_contents
+        .insert(uri.clone(), content.to_owned());
 
-    parser.add_content(utils::uri_to_path(uri)?, content);
-    global_state.file_results = parser.validate();
-    global_state.items_by_key = global_state
-        .file_results
+    // Excluded files are analyzed like virtual documents (e.g. when opened)
+    let excluded = match (uri.to_file_path(), &global_state.root_path) {
+        (Ok(path), Some(root_path)) => global_state.excludes.is_excluded(root_path, &path),
+        _ => false,
+    };
+
+    if excluded || utils::is_virtual_document(&uri) {
+        analyze_virtual_document(global_state, &uri);
+    } else {
+        global_state.parser.add_content(uri, content);
+        validate(global_state);
+    }
+
+    update_diagnostics(global_state)?;
+
+    Ok(())
+}
+
+/// Forget a virtual document (e.g. when closed), the files stay in the workspace
+pub fn close_document(global_state: &mut GlobalState, uri: &lsp_types::Url) -> Result<()> {
+    let uri = utils::normalize_uri(uri);
+    global_state.open_documents.remove(&uri);
+    if global_state.virtual_documents.remove(&uri).is_none() {
+        return Ok(());
+    }
+
+    global_state.file_contents.remove(&uri);
+
+    // Clear the diagnostics of the document
+    if !global_state.pull_diagnostics {
+        diagnostics::publish_empty_diagnostics(global_state, uri);
+    }
+
+    Ok(())
+}
+
+/// Resolve the settings from their sources and apply the changes (re-indexing the
+/// workspace if needed)
+pub fn apply_config(global_state: &mut GlobalState) -> Result<()> {
+    // Check the new settings before replacing the previous ones
+    let current_log_level = global_state.config.log_level.clone();
+    let result = global_state
+        .config_sources
+        .resolve()
+        .and_then(|new_config| {
+            let excludes = Excludes::new(&new_config.excludes)?;
+            if new_config.log_level != current_log_level {
+                log::set_level(new_config.log_level.as_deref())?;
+            }
+            Ok((new_config, excludes))
+        });
+
+    let (new_config, excludes) = match result {
+        Ok(it) => it,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {:#}", e);
+            global_state.show_message(
+                lsp_types::MessageType::ERROR,
+                format!(
+                    "AIDL: invalid configuration, the previous settings are kept: {:#}",
+                    e
+                ),
+            );
+            return Ok(());
+        }
+    };
+
+    if new_config == global_state.config {
+        return Ok(());
+    }
+
+    tracing::info!("New configuration: {:?}", new_config);
+    global_state.excludes = excludes;
+    let old_config = std::mem::replace(&mut global_state.config, new_config);
+    let config = &global_state.config;
+
+    // The workspace will be indexed with the new settings
+    if global_state.indexing_state == IndexingState::Idle {
+        return Ok(());
+    }
+
+    if old_config.requires_reindexing(config) {
+        return reindex(global_state);
+    }
+
+    if config.lints != old_config.lints {
+        validate(global_state);
+        update_diagnostics(global_state)?;
+    }
+
+    Ok(())
+}
+
+/// Load the project configuration file again (e.g. after a change)
+pub fn reload_project_config(global_state: &mut GlobalState) -> Result<()> {
+    let root_path = global_state
+        .root_path
+        .as_ref()
+        .context("No root path set")?;
+    match config::load_project_file(root_path) {
+        Ok(project_file) => global_state.config_sources.project_file = project_file,
+        Err(e) => {
+            tracing::error!("Could not load the project configuration: {:#}", e);
+            global_state.show_message(
+                lsp_types::MessageType::ERROR,
+                format!(
+                    "AIDL: could not load the project configuration, the previous settings are kept: {:#}",
+                    e
+                ),
+            );
+            return Ok(());
+        }
+    }
+
+    apply_config(global_state)
+}
+
+// Index the files of the workspace from scratch (the contents of the open documents are
+// kept)
+pub fn reindex(global_state: &mut GlobalState) -> Result<()> {
+    let old_uris: Vec<lsp_types::Url> = global_state.file_results.keys().cloned().collect();
+    let open_contents: Vec<(lsp_types::Url, String)> = global_state
+        .open_documents
+        .iter()
+        .filter_map(|uri| {
+            let content = global_state.file_contents.get(uri)?;
+            Some((uri.clone(), content.clone()))
+        })
+        .collect();
+
+    global_state.parser = Parser::new();
+    global_state.file_results.clear();
+    global_state.virtual_documents.clear();
+    global_state.file_contents.clear();
+
+    index(global_state)?;
+
+    // The open documents may be unsaved, or become virtual documents (i.e. excluded)
+    for (uri, content) in open_contents {
+        if global_state.file_contents.get(&uri) != Some(&content) {
+            update_content(global_state, &uri, &content)?;
+        }
+    }
+
+    // Clear the diagnostics of the files which are not indexed anymore
+    if !global_state.pull_diagnostics {
+        for uri in old_uris {
+            if !global_state.file_results.contains_key(&uri)
+                && !global_state.virtual_documents.contains_key(&uri)
+            {
+                diagnostics::publish_empty_diagnostics(global_state, uri);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Validate the content of the parser and run the workspace checks
+fn validate(global_state: &mut GlobalState) {
+    let _span = tracing::info_span!("validate").entered();
+    let start = Instant::now();
+
+    global_state.file_results = global_state.parser.validate();
+    for (uri, fr) in global_state.file_results.iter_mut() {
+        if let Some(content) = global_state.file_contents.get(uri) {
+            position_encoding::convert_file_result(fr, content, global_state.position_encoding);
+        }
+    }
+
+    // Iterate over sorted URIs so that the first definition of an item is deterministic
+    let mut uris: Vec<&lsp_types::Url> = global_state.file_results.keys().collect();
+    uris.sort();
+
+    let mut uris_by_key: HashMap<ast::ItemKey, Vec<lsp_types::Url>> = HashMap::new();
+    for uri in uris {
+        if let Some(ast) = &global_state.file_results[uri].ast {
+            uris_by_key
+                .entry(ast.get_key())
+                .or_default()
+                .push(uri.clone());
+        }
+    }
+
+    global_state.items_by_key = uris_by_key
         .iter()
-        .filter_map(|(id, fr)| fr.ast.as_ref().map(|f| (f.get_key(), id.clone())))
+        .map(|(key, uris)| (key.clone(), uris[0].clone()))
+        .collect();
+
+    global_state.import_roots = checks::get_import_roots(global_state);
+    {
+        let _span = tracing::info_span!("check_workspace").entered();
+        global_state.workspace_diagnostics = checks::check_workspace(global_state, &uris_by_key);
+    }
+
+    // The types of the virtual documents are resolved against the items of the
+    // workspace: they are analyzed again only if an item was added, removed or changed
+    // its kind
+    let item_kinds: HashMap<ast::ItemKey, ast::ItemKind> = global_state
+        .file_results
+        .values()
+        .filter_map(|fr| fr.ast.as_ref())
+        .map(|ast| (ast.get_key(), ast.item.get_kind()))
         .collect();
+    if item_kinds != global_state.item_kinds {
+        global_state.item_stubs_parser = get_item_stubs_parser(&item_kinds);
+        global_state.item_kinds = item_kinds;
+        let virtual_uris: Vec<lsp_types::Url> =
+            global_state.virtual_documents.keys().cloned().collect();
+        for uri in virtual_uris {
+            analyze_virtual_document(global_state, &uri);
+        }
+    }
+
+    global_state.metrics.record("validation", start.elapsed());
+}
 
-    notify_diagnostics(global_state)?;
+// Parse-only analysis of a virtual document: it is validated together with the stubs of
+// the items of the workspace (to resolve its types, without validating the files again)
+// but does not take part in the workspace itself
+fn analyze_virtual_document(global_state: &mut GlobalState, uri: &lsp_types::Url) {
+    let content = match global_state.file_contents.get(uri) {
+        Some(content) => content,
+        None => return,
+    };
+
+    let parser = &mut global_state.item_stubs_parser;
+    parser.add_content(uri.clone(), content);
+    let mut results = parser.validate();
+    parser.remove_content(uri.clone());
+
+    if let Some(mut fr) = results.remove(uri) {
+        position_encoding::convert_file_result(&mut fr, content, global_state.position_encoding);
+        global_state.virtual_documents.insert(uri.clone(), fr);
+    }
+}
+
+// Parser of minimal declarations of the given items (the validation of a file only depends
+// on the keys and kinds of the other items)
+fn get_item_stubs_parser(
+    item_kinds: &HashMap<ast::ItemKey, ast::ItemKind>,
+) -> Parser<lsp_types::Url> {
+    let mut parser = Parser::new();
+    for (key, kind) in item_kinds {
+        let (package, name) = match key.rsplit_once('.') {
+            Some(it) => it,
+            None => continue,
+        };
+        let declaration = match kind {
+            ast::ItemKind::Interface => format!("interface {} {{}}", name),
+            ast::ItemKind::Parcelable => format!("parcelable {} {{}}", name),
+            ast::ItemKind::Enum => format!("enum {} {{ STUB }}", name),
+        };
+        let content = format!("package {};\n{}\n", package, declaration);
+        match lsp_types::Url::parse(&format!("aidl-stub:{}", key)) {
+            Ok(uri) => parser.add_content(uri, &content),
+            Err(e) => tracing::warn!("Invalid stub URI for {}: {}", key, e),
+        }
+    }
+
+    parser
+}
+
+// Push the diagnostics, or let the client pull them if it supports it
+fn update_diagnostics(global_state: &mut GlobalState) -> Result<()> {
+    let _span = tracing::info_span!("update_diagnostics").entered();
+
+    if !global_state.pull_diagnostics {
+        return diagnostics::publish_diagnostics(global_state);
+    }
+
+    diagnostics::complete_workspace_report(global_state)?;
+    if global_state.diagnostics_refresh_support {
+        global_state.send_request::<lsp_ext::WorkspaceDiagnosticRefresh>((), |_, _| Ok(()));
+    }
 
     Ok(())
 }
 
-fn notify_diagnostics(global_state: &GlobalState) -> Result<()> {
-    for res in global_state.file_results.values() {
-        let path = &res.id;
-        let uri = utils::path_to_uri(path)?;
-
-        let diagnostics = res
-            .diagnostics
-            .iter()
-            .map(|d| {
-                let main_location = lsp_types::Location {
-                    uri: uri.clone(),
-                    range: utils::to_lsp_range(&d.range),
-                };
-
-                let mut related_infos = Vec::new();
-
-                // Note: do not add the context message which is redundant with the main message
-                //if let Some(ctx_msg) = &d.context_message {
-                //    related_infos.push(lsp_types::DiagnosticRelatedInformation {
-                //        location: main_location.clone(),
-                //        message: ctx_msg.clone(),
-                //    });
-                //}
-
-                if let Some(hint) = &d.hint {
-                    related_infos.push(lsp_types::DiagnosticRelatedInformation {
-                        location: main_location,
-                        message: hint.clone(),
-                    });
-                }
-
-                let related_infos = related_infos
-                    .into_iter()
-                    .chain(d.related_infos.iter().map(|ri| {
-                        lsp_types::DiagnosticRelatedInformation {
-                            location: lsp_types::Location {
-                                uri: uri.clone(),
-                                range: utils::to_lsp_range(&ri.range),
-                            },
-                            message: ri.message.clone(),
-                        }
-                    }))
-                    .collect();
-
-                lsp_types::Diagnostic::new(
-                    utils::to_lsp_range(&d.range),
-                    Some(match d.kind {
-                        aidl_parser::diagnostic::DiagnosticKind::Error => {
-                            lsp_types::DiagnosticSeverity::ERROR
-                        }
-                        aidl_parser::diagnostic::DiagnosticKind::Warning => {
-                            lsp_types::DiagnosticSeverity::WARNING
-                        }
-                    }),
-                    Some(lsp_types::NumberOrString::String("aidl".to_owned())),
-                    None,
-                    d.message.clone(),
-                    Some(related_infos),
-                    None,
-                )
+/// Workspace `/ws` indexed from the given files (paths relative to the root and
+/// contents), with the receiver of the messages sent to the client
+#[cfg(test)]
+pub fn index_test_files(
+    files: &[(&str, &str)],
+) -> (
+    GlobalState,
+    crossbeam::channel::Receiver<lsp_server::Message>,
+) {
+    let (sender, receiver) = crossbeam::channel::unbounded();
+    let mut global_state = GlobalState::new(sender);
+    let root_path = std::path::PathBuf::from("/ws");
+
+    for (path, content) in files {
+        let uri = utils::path_to_uri(&root_path.join(path)).unwrap();
+        global_state.parser.add_content(uri.clone(), content);
+        global_state.file_contents.insert(uri, content.to_string());
+    }
+
+    global_state.root_path = Some(root_path);
+    validate(&mut global_state);
+    global_state.indexing_state = IndexingState::Indexed;
+
+    (global_state, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_stubs() {
+        let item_kinds = HashMap::from([
+            ("a.IFoo".to_owned(), ast::ItemKind::Interface),
+            ("a.b.Bar".to_owned(), ast::ItemKind::Parcelable),
+            ("c.Baz".to_owned(), ast::ItemKind::Enum),
+        ]);
+
+        let results = get_item_stubs_parser(&item_kinds).validate();
+        assert_eq!(results.len(), 3);
+        let stub_kinds: HashMap<ast::ItemKey, ast::ItemKind> = results
+            .values()
+            .map(|fr| {
+                assert!(fr.diagnostics.is_empty(), "{:?}", fr.diagnostics);
+                let ast = fr.ast.as_ref().unwrap();
+                (ast.get_key(), ast.item.get_kind())
             })
             .collect();
-
-        let notif = lsp_server::Notification::new(
-            lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
-            lsp_types::PublishDiagnosticsParams {
-                uri: uri.clone(),
-                diagnostics,
-                version: None,
-            },
-        );
-        global_state
-            .sender
-            .send(lsp_server::Message::Notification(notif))
-            .unwrap();
+        assert_eq!(stub_kinds, item_kinds);
     }
 
-    Ok(())
+    #[test]
+    fn virtual_document() {
+        let (mut global_state, _receiver) = index_test_files(&[
+            ("a/IFoo.aidl", "package a;\ninterface IFoo {}\n"),
+            ("a/Bar.aidl", "package a;\nparcelable Bar {}\n"),
+        ]);
+        let uri = lsp_types::Url::parse("untitled:Untitled-1").unwrap();
+
+        // Resolved against the items of the workspace, without being part of it
+        let content = "package a;\nimport a.Bar;\nimport a.IFoo;\ninterface IBaz {\n    void f(in Bar bar, IFoo foo);\n}\n";
+        update_content(&mut global_state, &uri, content).unwrap();
+        let fr = &global_state.virtual_documents[&uri];
+        assert!(fr.diagnostics.is_empty(), "{:?}", fr.diagnostics);
+        assert!(!global_state.file_results.contains_key(&uri));
+        assert!(!global_state.item_kinds.contains_key("a.IBaz"));
+
+        let content = "package a;\ninterface IBaz {\n    void f(in Qux qux);\n}\n";
+        update_content(&mut global_state, &uri, content).unwrap();
+        assert_eq!(global_state.virtual_documents[&uri].diagnostics.len(), 1);
+
+        // Analyzed again when the items of the workspace change
+        let bar_uri = utils::path_to_uri(std::path::Path::new("/ws/a/Bar.aidl")).unwrap();
+        let content = "package a;\nimport a.Bar;\ninterface IBaz {\n    void f(in Bar bar);\n}\n";
+        update_content(&mut global_state, &uri, content).unwrap();
+        assert!(global_state.virtual_documents[&uri].diagnostics.is_empty());
+        update_content(
+            &mut global_state,
+            &bar_uri,
+            "package a;\nparcelable Qux {}\n",
+        )
+        .unwrap();
+        assert_eq!(global_state.virtual_documents[&uri].diagnostics.len(), 1);
+    }
 }
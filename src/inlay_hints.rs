@@ -0,0 +1,67 @@
+use aidl_parser::ast;
+
+use crate::lsp_ext::InlayHint;
+use crate::utils;
+
+/// Binder transaction code of the first method (`IBinder.FIRST_CALL_TRANSACTION`)
+const FIRST_CALL_TRANSACTION: u32 = 1;
+
+/// Inlay hints for the transaction codes of the methods and the implicit values
+/// of the enum elements
+pub fn get_inlay_hints(ast: &ast::Aidl, content: &str, range: &lsp_types::Range) -> Vec<InlayHint> {
+    let hints = match &ast.item {
+        ast::Item::Interface(i) => get_transaction_code_hints(i),
+        ast::Item::Enum(e) => get_enum_value_hints(e, content),
+        ast::Item::Parcelable(_) => Vec::new(),
+    };
+
+    hints
+        .into_iter()
+        .filter(|h| range.start <= h.position && h.position <= range.end)
+        .collect()
+}
+
+/// Transaction codes of the methods of an interface: based on the explicit id of the
+/// methods if given or on their order otherwise
+pub fn get_transaction_codes(interface: &ast::Interface) -> Vec<(&ast::Method, u32)> {
+    interface
+        .elements
+        .iter()
+        .filter_map(|el| el.as_method())
+        .enumerate()
+        .map(|(index, m)| {
+            let id = m.value.unwrap_or(index as u32);
+            (m, FIRST_CALL_TRANSACTION + id)
+        })
+        .collect()
+}
+
+fn get_transaction_code_hints(interface: &ast::Interface) -> Vec<InlayHint> {
+    get_transaction_codes(interface)
+        .into_iter()
+        .map(|(m, code)| InlayHint {
+            position: utils::to_lsp_range(&m.full_range).end,
+            label: format!("tx: {}", code),
+            tooltip: Some(String::from("Binder transaction code")),
+            padding_left: Some(true),
+            padding_right: None,
+        })
+        .collect()
+}
+
+fn get_enum_value_hints(e: &ast::Enum, content: &str) -> Vec<InlayHint> {
+    e.elements
+        .iter()
+        .zip(utils::get_enum_element_values(e, content))
+        .filter(|(el, _)| el.value.is_none())
+        .filter_map(|(el, value)| {
+            value.map(|v| InlayHint {
+                position: utils::to_lsp_range(&el.symbol_range).end,
+                label: format!("= {}", v),
+                tooltip: Some(String::from("Implicit value")),
+                padding_left: Some(true),
+                padding_right: None,
+            })
+        })
+        .collect()
+}
@@ -1,3 +1,293 @@
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use lsp_server::Message;
+use lsp_types::notification::{self, Notification};
+use tracing::field::{Field, Visit};
+use tracing::metadata::LevelFilter;
+use tracing::Level;
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+use crate::lsp_ext;
+
+/// Environment variable defining the maximum level of the logs (if not given on the
+/// command line)
+pub const LOG_LEVEL_ENV: &str = "AIDL_LSP_LOG";
+
+// The log file is rolled over when it exceeds this size (keeping a few old files,
+// e.g. `aidl-lsp.log.1`)
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_LOG_FILE_BACKUPS: usize = 3;
+
+/// Options of the logger (from the command line or the environment)
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    /// Maximum level of the logs (`INFO` if not set)
+    pub level: Option<LevelFilter>,
+    /// File where the logs are written in addition to stderr
+    pub file: Option<PathBuf>,
+    /// One JSON object per log instead of text
+    pub json: bool,
+}
+
+struct Logger {
+    level_handle: reload::Handle<LevelFilter, Registry>,
+    /// Level of the options, used when the settings do not define any
+    default_level: LevelFilter,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+// Connection to the client (once created) and trace level requested by the client
+// (`$/setTrace`)
+static CLIENT: Mutex<Option<crossbeam::channel::Sender<Message>>> = Mutex::new(None);
+static TRACE: AtomicU8 = AtomicU8::new(TRACE_OFF);
+
+const TRACE_OFF: u8 = 0;
+const TRACE_MESSAGES: u8 = 1;
+const TRACE_VERBOSE: u8 = 2;
+
+/// Install the logger: logs written to stderr (and to the log file, if any) and
+/// forwarded to the client
+pub fn init(options: &LogOptions) -> Result<()> {
+    let default_level = options.level.unwrap_or(LevelFilter::INFO);
+    let (level_layer, level_handle) = reload::Layer::new(default_level);
+
+    let writer = match &options.file {
+        Some(path) => {
+            let file = RollingFile::open(path)
+                .with_context(|| format!("Could not open the log file {:?}", path))?;
+            BoxMakeWriter::new(std::io::stderr.and(Mutex::new(file)))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+
+    let (text_layer, json_layer) = if options.json {
+        let layer = tracing_subscriber::fmt::layer().json().with_writer(writer);
+        (None, Some(layer))
+    } else {
+        let layer = tracing_subscriber::fmt::layer()
+            .event_format(LoggerFormatter)
+            .with_ansi(options.file.is_none())
+            .with_writer(writer);
+        (Some(layer), None)
+    };
+
+    tracing_subscriber::registry()
+        .with(level_layer)
+        .with(text_layer)
+        .with(json_layer)
+        .with(ClientLayer)
+        .init();
+
+    let _ = LOGGER.set(Logger {
+        level_handle,
+        default_level,
+    });
+
+    Ok(())
+}
+
+/// Change the maximum level of the logs (`off`, `error`, `warn`, `info`, `debug` or
+/// `trace`), or restore the level of the options
+pub fn set_level(level: Option<&str>) -> Result<()> {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return Ok(()),
+    };
+
+    let level = match level {
+        Some(level) => level
+            .parse()
+            .map_err(|_| anyhow::format_err!("Invalid log level `{}`", level))?,
+        None => logger.default_level,
+    };
+
+    logger.level_handle.reload(level)?;
+
+    Ok(())
+}
+
+/// Forward the logs to the client from now on (`None` to stop, which is required to
+/// close the connection)
+pub fn set_client(sender: Option<crossbeam::channel::Sender<Message>>) {
+    if let Ok(mut client) = CLIENT.lock() {
+        *client = sender;
+    }
+}
+
+/// Send the logs as traces (`$/logTrace`) to the client, depending on the trace value
+/// requested by the client
+pub fn set_trace(trace: lsp_types::TraceOption) {
+    let trace = match trace {
+        lsp_types::TraceOption::Off => TRACE_OFF,
+        lsp_types::TraceOption::Messages => TRACE_MESSAGES,
+        lsp_types::TraceOption::Verbose => TRACE_VERBOSE,
+    };
+
+    TRACE.store(trace, Ordering::Relaxed);
+}
+
+// Forward the logs of the server to the client: warnings and errors to the log of the
+// client (`window/logMessage`) and all the logs as traces (if enabled)
+struct ClientLayer;
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for ClientLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let metadata = event.metadata();
+        let level = *metadata.level();
+        let trace = TRACE.load(Ordering::Relaxed);
+
+        // Note: the logs of the dependencies are ignored, in particular the ones of the IO
+        // threads of lsp-server which would block when sending a message
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+            || (level > Level::WARN && trace == TRACE_OFF)
+        {
+            return;
+        }
+
+        let sender = match CLIENT.lock().ok().and_then(|client| client.clone()) {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        if level <= Level::WARN {
+            let typ = if level == Level::ERROR {
+                lsp_types::MessageType::ERROR
+            } else {
+                lsp_types::MessageType::WARNING
+            };
+
+            send_notification::<notification::LogMessage>(
+                &sender,
+                lsp_types::LogMessageParams {
+                    typ,
+                    message: visitor.message.clone(),
+                },
+            );
+        }
+
+        if trace != TRACE_OFF {
+            let verbose = (trace == TRACE_VERBOSE).then(|| {
+                format!(
+                    "{} ({}:{})",
+                    metadata.target(),
+                    metadata.file().unwrap_or_default(),
+                    metadata.line().unwrap_or_default()
+                )
+            });
+
+            send_notification::<lsp_ext::LogTrace>(
+                &sender,
+                lsp_ext::LogTraceParams {
+                    message: format!("[{}] {}", level, visitor.message),
+                    verbose,
+                },
+            );
+        }
+    }
+}
+
+fn send_notification<N: Notification>(
+    sender: &crossbeam::channel::Sender<Message>,
+    params: N::Params,
+) {
+    let notif = lsp_server::Notification::new(N::METHOD.to_owned(), params);
+
+    // The client may be gone already
+    let _ = sender.send(Message::Notification(notif));
+}
+
+// Message of an event, followed by its other fields (if any)
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+
+        let _ = match field.name() {
+            "message" => write!(self.message, "{:?}", value),
+            name => write!(self.message, "{}={:?}", name, value),
+        };
+    }
+}
+
+/// Log file which is rolled over when it exceeds the maximum size
+struct RollingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RollingFile {
+    fn open(path: &std::path::Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RollingFile {
+            path: path.to_owned(),
+            file,
+            size,
+        })
+    }
+
+    // Rename the files (`aidl-lsp.log` -> `aidl-lsp.log.1` -> `aidl-lsp.log.2`...) and
+    // start a new one
+    fn roll_over(&mut self) -> io::Result<()> {
+        let backup_path = |index: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", index));
+            PathBuf::from(path)
+        };
+
+        for index in (1..MAX_LOG_FILE_BACKUPS).rev() {
+            let _ = std::fs::rename(backup_path(index), backup_path(index + 1));
+        }
+        std::fs::rename(&self.path, backup_path(1))?;
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_LOG_FILE_SIZE {
+            self.roll_over()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct LoggerFormatter;
 
@@ -0,0 +1,229 @@
+//! LSP extensions and LSP 3.17 features which are not supported by lsp-types yet
+
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+pub enum InlayHintRequest {}
+
+impl Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub range: lsp_types::Range,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: lsp_types::Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_right: Option<bool>,
+}
+
+pub enum PrepareTypeHierarchyRequest {}
+
+impl Request for PrepareTypeHierarchyRequest {
+    type Params = TypeHierarchyPrepareParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+}
+
+pub enum TypeHierarchySupertypesRequest {}
+
+impl Request for TypeHierarchySupertypesRequest {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+}
+
+pub enum TypeHierarchySubtypesRequest {}
+
+impl Request for TypeHierarchySubtypesRequest {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyPrepareParams {
+    #[serde(flatten)]
+    pub text_document_position_params: lsp_types::TextDocumentPositionParams,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyItem {
+    pub name: String,
+    pub kind: lsp_types::SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: lsp_types::Url,
+    pub range: lsp_types::Range,
+    pub selection_range: lsp_types::Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+pub enum DocumentDiagnosticRequest {}
+
+impl Request for DocumentDiagnosticRequest {
+    type Params = DocumentDiagnosticParams;
+    type Result = DocumentDiagnosticReport;
+    const METHOD: &'static str = "textDocument/diagnostic";
+}
+
+pub enum WorkspaceDiagnosticRequest {}
+
+impl Request for WorkspaceDiagnosticRequest {
+    type Params = WorkspaceDiagnosticParams;
+    type Result = WorkspaceDiagnosticReport;
+    const METHOD: &'static str = "workspace/diagnostic";
+}
+
+pub enum WorkspaceDiagnosticRefresh {}
+
+impl Request for WorkspaceDiagnosticRefresh {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "workspace/diagnostic/refresh";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub identifier: Option<String>,
+    pub previous_result_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DocumentDiagnosticReport {
+    Full(FullDocumentDiagnosticReport),
+    Unchanged(UnchangedDocumentDiagnosticReport),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullDocumentDiagnosticReport {
+    pub result_id: Option<String>,
+    pub items: Vec<lsp_types::Diagnostic>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnchangedDocumentDiagnosticReport {
+    pub result_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosticParams {
+    pub identifier: Option<String>,
+    pub previous_result_ids: Vec<PreviousResultId>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousResultId {
+    pub uri: lsp_types::Url,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosticReport {
+    pub items: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDocumentDiagnosticReport {
+    pub uri: lsp_types::Url,
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    pub report: DocumentDiagnosticReport,
+}
+
+pub enum SetTrace {}
+
+impl Notification for SetTrace {
+    type Params = SetTraceParams;
+    const METHOD: &'static str = "$/setTrace";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetTraceParams {
+    pub value: lsp_types::TraceOption,
+}
+
+pub enum LogTrace {}
+
+impl Notification for LogTrace {
+    type Params = LogTraceParams;
+    const METHOD: &'static str = "$/logTrace";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LogTraceParams {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+/// Status of the server (e.g. for the status bar of the client)
+pub enum StatusRequest {}
+
+impl Request for StatusRequest {
+    type Params = ();
+    type Result = ServerStatus;
+    const METHOD: &'static str = "aidl-lsp/status";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    /// `idle`, `indexing`, `indexed` or `error`
+    pub indexing_state: String,
+    pub indexed_files: usize,
+    pub virtual_documents: usize,
+    /// Number of errors reported by the parser (syntax and type errors)
+    pub errors: usize,
+    /// Approximate memory used by the documents, in bytes
+    pub memory_estimate: usize,
+    pub last_index_duration_ms: Option<f64>,
+    /// Latencies by request or notification method (and internal phase)
+    pub latencies: BTreeMap<String, LatencyStats>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyStats {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
@@ -1,44 +1,79 @@
 use anyhow::{Context, Result};
 use lsp_server::{Connection, Message};
 use lsp_types::{notification, request};
-use tracing::metadata::LevelFilter;
 
+mod backends;
+mod call_hierarchy;
+mod checks;
+mod cli;
+mod code_actions;
+mod code_lens;
+mod config;
+mod crash;
+mod diagnostics;
 mod dispatch;
+mod document_highlight;
+mod document_link;
 mod error;
+mod excludes;
+mod folding_range;
 mod handlers;
+mod hover;
 mod indexing;
+mod inlay_hints;
 mod log;
+mod lsp_ext;
+mod metrics;
+mod position_encoding;
+mod selection_range;
+mod signature_help;
 mod state;
+mod transport;
+mod type_hierarchy;
 mod utils;
+mod values;
+mod workspace_symbol;
 
 use dispatch::{NotificationDispatcher, RequestDispatcher};
-use state::GlobalState;
+use position_encoding::PositionEncoding;
+use state::{GlobalState, IndexingState};
 
 fn main() -> Result<()> {
-    let subscriber_builder = tracing_subscriber::fmt()
-        .event_format(log::LoggerFormatter)
-        .with_writer(std::io::stderr)
-        .with_max_level(LevelFilter::INFO);
+    let args = match cli::parse_args()? {
+        Some(args) => args,
+        None => return Ok(()),
+    };
 
-    subscriber_builder.init();
+    log::init(&args.log)?;
 
     tracing::info!("Starting AIDL LSP server");
 
     // Create the transport
-    let (connection, io_threads) = Connection::stdio();
-
-    // Server capabilities
-    let server_capabilities = serde_json::to_value(&server_capabilities()).unwrap();
+    let (connection, io_threads) = transport::create_connection(&args.transport)?;
+    log::set_client(Some(connection.sender.clone()));
 
     tracing::info!("Initializing connection");
-    let init_params = connection.initialize(server_capabilities)?;
+    let (initialize_id, init_params) = connection.initialize_start()?;
     tracing::info!("Init params: {}", init_params);
 
+    // Server capabilities (depending on the position encodings supported by the client)
+    let position_encoding = PositionEncoding::negotiate(&init_params["capabilities"]);
+    tracing::info!("Position encoding: {}", position_encoding.as_str());
+    let mut server_capabilities = serde_json::to_value(server_capabilities()).unwrap();
+    add_lsp_ext_capabilities(&mut server_capabilities, position_encoding);
+
+    connection.initialize_finish(
+        initialize_id,
+        serde_json::json!({ "capabilities": server_capabilities }),
+    )?;
+
     // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
     tracing::info!("Starting main loop");
 
-    if let Err(e) = main_loop(connection, init_params) {
-        tracing::error!("Error in main loop: {}", e);
+    let result = main_loop(connection, init_params, position_encoding);
+    log::set_client(None);
+    if let Err(e) = &result {
+        tracing::error!("Error in main loop: {:#}", e);
     }
 
     if let Err(e) = io_threads.join() {
@@ -46,55 +81,167 @@ fn main() -> Result<()> {
     }
 
     tracing::info!("shutting down server");
+
+    // Exit code 1 if the client exited without the shutdown handshake
+    if result.is_err() {
+        std::process::exit(1);
+    }
+
     Ok(())
 }
 
-fn main_loop(connection: Connection, init_params: serde_json::Value) -> Result<()> {
-    let init_params: lsp_types::InitializeParams = serde_json::from_value(init_params).unwrap();
+fn main_loop(
+    connection: Connection,
+    init_params: serde_json::Value,
+    position_encoding: PositionEncoding,
+) -> Result<()> {
     let mut global_state = GlobalState::new(connection.sender.clone());
+    global_state.position_encoding = position_encoding;
+
+    // LSP 3.17 client capabilities (not supported by lsp-types yet)
+    let capabilities = &init_params["capabilities"];
+    global_state.pull_diagnostics = capabilities["textDocument"]["diagnostic"].is_object();
+    global_state.diagnostics_refresh_support = capabilities["workspace"]["diagnostics"]
+        ["refreshSupport"]
+        .as_bool()
+        .unwrap_or(false);
+    global_state.configuration_support = capabilities["workspace"]["configuration"]
+        .as_bool()
+        .unwrap_or(false);
+    global_state.watched_files_registration_support = capabilities["workspace"]
+        ["didChangeWatchedFiles"]["dynamicRegistration"]
+        .as_bool()
+        .unwrap_or(false);
+
+    let init_params: lsp_types::InitializeParams = serde_json::from_value(init_params).unwrap();
+    log::set_trace(init_params.trace.unwrap_or_default());
 
     let root_uri = init_params.root_uri.context("Missing root URI")?;
     let file_path = root_uri
         .to_file_path()
         .map_err(|_| anyhow::format_err!("Invalid root path (not a file path): {}", root_uri))?;
     tracing::info!("root path = {:?}", file_path);
+    global_state.config_sources.initialization_options = init_params.initialization_options;
+    match config::load_project_file(&file_path) {
+        Ok(project_file) => global_state.config_sources.project_file = project_file,
+        Err(e) => {
+            tracing::error!("Could not load the project configuration: {:#}", e);
+            global_state.show_message(
+                lsp_types::MessageType::ERROR,
+                format!("AIDL: could not load the project configuration: {:#}", e),
+            );
+        }
+    }
     global_state.root_path = Some(file_path);
 
-    // Indexing (TODO: incl. progress support)
-    indexing::index(&mut global_state)?;
+    if let Err(e) = indexing::apply_config(&mut global_state) {
+        tracing::error!("Invalid configuration: {:#}", e);
+    }
+
+    // Pull the settings of the client (if supported) before indexing
+    // Note: the `initialized` notification is consumed by `Connection::initialize_finish()`
+    if let Err(e) = handlers::handle_initialized(&mut global_state, lsp_types::InitializedParams {})
+    {
+        tracing::error!("Could not handle initialized notification: {:#}", e);
+    }
+    index_when_configured(&mut global_state);
 
     for msg in &connection.receiver {
         tracing::trace!("got msg: {:?}", msg);
 
-        match msg {
-            Message::Request(req) => {
-                RequestDispatcher {
-                    global_state: &mut global_state,
-                    req: Some(req),
-                }
-                .on::<request::WorkspaceSymbol>(handlers::handle_workspace_symbol)?
-                .on::<request::DocumentSymbolRequest>(handlers::handle_document_symbol)?
-                .on::<request::HoverRequest>(handlers::handle_hover)?
-                .on::<request::GotoDefinition>(handlers::handle_goto_definition)?
-                .finish();
+        match &msg {
+            Message::Request(req) if connection.handle_shutdown(req)? => return Ok(()),
+            Message::Notification(notif) if notif.method == "exit" => break,
+            _ => (),
+        }
+
+        // Errors are logged and the server keeps running
+        if let Err(e) = handle_message(&mut global_state, msg) {
+            tracing::error!("Could not handle message: {:#}", e);
+        }
+
+        index_when_configured(&mut global_state);
+    }
+
+    anyhow::bail!("Client exited without shutdown request")
+}
+
+// Index the workspace once the settings of the client are known (answered or rejected
+// `workspace/configuration`), so that it is not indexed again with the pulled settings
+fn index_when_configured(global_state: &mut GlobalState) {
+    if global_state.indexing_state != IndexingState::Idle
+        || global_state.is_pending::<request::WorkspaceConfiguration>()
+    {
+        return;
+    }
+
+    // TODO: progress support
+    if let Err(e) = indexing::index(global_state) {
+        tracing::error!("Indexing failed: {:#}", e);
+        global_state.show_message(
+            lsp_types::MessageType::ERROR,
+            format!("AIDL: indexing failed: {:#}", e),
+        );
+    }
+}
+
+fn handle_message(global_state: &mut GlobalState, msg: Message) -> Result<()> {
+    match msg {
+        Message::Request(req) => {
+            RequestDispatcher {
+                global_state,
+                req: Some(req),
             }
-            Message::Response(_) => todo!(),
-            Message::Notification(notif) => {
-                NotificationDispatcher {
-                    connection: &connection,
-                    global_state: &mut global_state,
-                    notif: Some(notif),
-                }
-                .on::<notification::Initialized>(handlers::handle_initialized)?
-                .on::<notification::DidChangeTextDocument>(
-                    handlers::handle_did_change_text_document,
-                )?
-                .on::<notification::DidOpenTextDocument>(handlers::handle_did_open_text_document)?
-                .on::<notification::DidSaveTextDocument>(handlers::handle_did_save_text_document)?
-                .finish();
+            .on::<request::WorkspaceSymbol>(handlers::handle_workspace_symbol)?
+            .on::<request::DocumentSymbolRequest>(handlers::handle_document_symbol)?
+            .on::<request::HoverRequest>(handlers::handle_hover)?
+            .on::<request::GotoDefinition>(handlers::handle_goto_definition)?
+            .on::<request::DocumentHighlightRequest>(handlers::handle_document_highlight)?
+            .on::<request::DocumentLinkRequest>(handlers::handle_document_link)?
+            .on::<request::FoldingRangeRequest>(handlers::handle_folding_range)?
+            .on::<request::SelectionRangeRequest>(handlers::handle_selection_range)?
+            .on::<request::CodeActionRequest>(handlers::handle_code_action)?
+            .on::<request::ExecuteCommand>(handlers::handle_execute_command)?
+            .on::<request::CodeLensRequest>(handlers::handle_code_lens)?
+            .on::<lsp_ext::InlayHintRequest>(handlers::handle_inlay_hint)?
+            .on::<request::SignatureHelpRequest>(handlers::handle_signature_help)?
+            .on::<lsp_ext::DocumentDiagnosticRequest>(handlers::handle_document_diagnostic)?
+            .on::<lsp_ext::StatusRequest>(handlers::handle_status)?
+            .on_deferred::<lsp_ext::WorkspaceDiagnosticRequest>(
+                handlers::handle_workspace_diagnostic,
+            )?
+            .on::<request::CallHierarchyPrepare>(handlers::handle_prepare_call_hierarchy)?
+            .on::<request::CallHierarchyIncomingCalls>(
+                handlers::handle_call_hierarchy_incoming_calls,
+            )?
+            .on::<request::CallHierarchyOutgoingCalls>(
+                handlers::handle_call_hierarchy_outgoing_calls,
+            )?
+            .on::<lsp_ext::PrepareTypeHierarchyRequest>(handlers::handle_prepare_type_hierarchy)?
+            .on::<lsp_ext::TypeHierarchySupertypesRequest>(
+                handlers::handle_type_hierarchy_supertypes,
+            )?
+            .on::<lsp_ext::TypeHierarchySubtypesRequest>(handlers::handle_type_hierarchy_subtypes)?
+            .finish();
+        }
+        Message::Response(resp) => global_state.complete_request(resp)?,
+        Message::Notification(notif) => {
+            NotificationDispatcher {
+                global_state,
+                notif: Some(notif),
             }
+            .on::<notification::DidChangeTextDocument>(handlers::handle_did_change_text_document)?
+            .on::<notification::DidOpenTextDocument>(handlers::handle_did_open_text_document)?
+            .on::<notification::DidSaveTextDocument>(handlers::handle_did_save_text_document)?
+            .on::<notification::DidCloseTextDocument>(handlers::handle_did_close_text_document)?
+            .on::<notification::DidChangeConfiguration>(handlers::handle_did_change_configuration)?
+            .on::<notification::DidChangeWatchedFiles>(handlers::handle_did_change_watched_files)?
+            .on::<notification::Cancel>(handlers::handle_cancel_request)?
+            .on::<lsp_ext::SetTrace>(handlers::handle_set_trace)?
+            .finish();
         }
     }
+
     Ok(())
 }
 
@@ -103,12 +250,40 @@ fn server_capabilities() -> lsp_types::ServerCapabilities {
 
     ServerCapabilities {
         definition_provider: Some(lsp_types::OneOf::Left(true)),
+        document_highlight_provider: Some(lsp_types::OneOf::Left(true)),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
         text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Kind(
             lsp_types::TextDocumentSyncKind::FULL,
         )),
         document_symbol_provider: Some(lsp_types::OneOf::Left(true)),
         hover_provider: Some(HoverProviderCapability::Simple(true)),
         workspace_symbol_provider: Some(lsp_types::OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(Vec::from([
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+            ])),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+            resolve_provider: None,
+        })),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(Vec::from(["(".to_owned(), "=".to_owned()])),
+            retrigger_characters: Some(Vec::from([",".to_owned()])),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: Vec::from([code_actions::ORGANIZE_IMPORTS_COMMAND.to_owned()]),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
         workspace: Some(lsp_types::WorkspaceServerCapabilities {
             workspace_folders: Some(lsp_types::WorkspaceFoldersServerCapabilities {
                 supported: Some(false),
@@ -126,3 +301,18 @@ fn server_capabilities() -> lsp_types::ServerCapabilities {
         ..ServerCapabilities::default()
     }
 }
+
+// Capabilities which are not supported by lsp-types yet (see lsp_ext)
+fn add_lsp_ext_capabilities(
+    capabilities: &mut serde_json::Value,
+    position_encoding: PositionEncoding,
+) {
+    capabilities["positionEncoding"] = serde_json::json!(position_encoding.as_str());
+    capabilities["inlayHintProvider"] = serde_json::Value::Bool(true);
+    capabilities["typeHierarchyProvider"] = serde_json::Value::Bool(true);
+    capabilities["diagnosticProvider"] = serde_json::json!({
+        "identifier": "aidl",
+        "interFileDependencies": true,
+        "workspaceDiagnostics": true,
+    });
+}
@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use aidl_parser::diagnostic::{Diagnostic, DiagnosticKind};
+
+use crate::lsp_ext::{LatencyStats, ServerStatus};
+use crate::state::{GlobalState, IndexingState};
+
+// Logarithmic buckets of the histograms: 4 per power of 2 (i.e. a relative error of
+// the percentiles below 19%), from 1 µs to about 70 minutes
+const BUCKETS_PER_DOUBLING: f64 = 4.0;
+const BUCKET_COUNT: usize = 128;
+
+// Approximate size of an AST relative to the size of its source (the serialized ASTs of
+// typical files are 12 to 15 times larger)
+const AST_SIZE_RATIO: usize = 12;
+
+/// Timings of the server: latency of the requests and notifications (by method) and
+/// of the internal phases (e.g. `validation`)
+#[derive(Default)]
+pub struct Metrics {
+    latencies: HashMap<&'static str, Histogram>,
+    pub last_index_duration: Option<Duration>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        self.latencies.entry(name).or_default().record(duration);
+    }
+}
+
+/// Histogram of durations
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: vec![0; BUCKET_COUNT],
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros().max(1) as f64;
+        let index = (micros.log2() * BUCKETS_PER_DOUBLING) as usize;
+
+        self.buckets[index.min(BUCKET_COUNT - 1)] += 1;
+        self.count += 1;
+        self.sum += duration;
+        self.max = self.max.max(duration);
+    }
+
+    // Upper bound of the bucket containing the given percentile (0..1)
+    fn percentile(&self, p: f64) -> Duration {
+        let rank = ((p * self.count as f64).ceil() as u64).max(1);
+
+        let mut cumulated = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            cumulated += count;
+            if cumulated >= rank {
+                let upper_bound = 2f64.powf((index + 1) as f64 / BUCKETS_PER_DOUBLING);
+                return Duration::from_secs_f64(upper_bound / 1e6).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    fn get_stats(&self) -> LatencyStats {
+        LatencyStats {
+            count: self.count,
+            mean_ms: to_ms(self.sum) / self.count.max(1) as f64,
+            p50_ms: to_ms(self.percentile(0.5)),
+            p90_ms: to_ms(self.percentile(0.9)),
+            p99_ms: to_ms(self.percentile(0.99)),
+            max_ms: to_ms(self.max),
+        }
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Status of the server (`aidl-lsp/status`)
+pub fn get_status(global_state: &GlobalState) -> ServerStatus {
+    let indexing_state = match global_state.indexing_state {
+        IndexingState::Idle => "idle",
+        IndexingState::Indexing => "indexing",
+        IndexingState::Indexed => "indexed",
+        IndexingState::Error => "error",
+    };
+
+    let results = global_state
+        .file_results
+        .values()
+        .chain(global_state.virtual_documents.values());
+
+    let errors = results
+        .clone()
+        .flat_map(|fr| &fr.diagnostics)
+        .filter(|d| d.kind == DiagnosticKind::Error)
+        .count();
+
+    // Rough estimate: the contents, their ASTs and the diagnostics
+    let memory_estimate = global_state
+        .file_contents
+        .values()
+        .map(|content| content.len() * (1 + AST_SIZE_RATIO))
+        .chain(results.flat_map(|fr| &fr.diagnostics).map(|d| {
+            std::mem::size_of::<Diagnostic>()
+                + d.message.len()
+                + d.context_message.as_ref().map_or(0, String::len)
+        }))
+        .sum();
+
+    let latencies: BTreeMap<String, LatencyStats> = global_state
+        .metrics
+        .latencies
+        .iter()
+        .map(|(name, histogram)| (name.to_string(), histogram.get_stats()))
+        .collect();
+
+    ServerStatus {
+        indexing_state: indexing_state.to_owned(),
+        indexed_files: global_state.file_results.len(),
+        virtual_documents: global_state.virtual_documents.len(),
+        errors,
+        memory_estimate,
+        last_index_duration_ms: global_state.metrics.last_index_duration.map(to_ms),
+        latencies,
+    }
+}
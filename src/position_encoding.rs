@@ -0,0 +1,373 @@
+use aidl_parser::{ast, diagnostic, ParseFileResult};
+
+/// Unit of the columns of the LSP positions (`positionEncoding` of LSP 3.17, UTF-16 if
+/// not negotiated)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Choose the encoding among the ones supported by the client: UTF-8 if possible
+    /// (column = bytes), otherwise the first supported one in order of preference
+    pub fn negotiate(client_capabilities: &serde_json::Value) -> Self {
+        let client_encodings: Vec<PositionEncoding> = client_capabilities["general"]
+            ["positionEncodings"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.as_str().and_then(PositionEncoding::from_str))
+            .collect();
+
+        if client_encodings.contains(&PositionEncoding::Utf8) {
+            return PositionEncoding::Utf8;
+        }
+
+        client_encodings.first().copied().unwrap_or_default()
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "utf-8" => Some(PositionEncoding::Utf8),
+            "utf-16" => Some(PositionEncoding::Utf16),
+            "utf-32" => Some(PositionEncoding::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PositionEncoding::Utf8 => "utf-8",
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf32 => "utf-32",
+        }
+    }
+
+    /// Number of code units of a string
+    pub fn count_units(self, s: &str) -> usize {
+        match self {
+            PositionEncoding::Utf8 => s.len(),
+            PositionEncoding::Utf16 => s.encode_utf16().count(),
+            PositionEncoding::Utf32 => s.chars().count(),
+        }
+    }
+
+    /// Byte offset of the given column (in code units) of a line, or the length of the
+    /// line if the column is beyond its end
+    pub fn to_byte_offset(self, line: &str, column: usize) -> usize {
+        let mut units = 0;
+        for (index, c) in line.char_indices() {
+            if units >= column {
+                return index;
+            }
+            units += match self {
+                PositionEncoding::Utf8 => c.len_utf8(),
+                PositionEncoding::Utf16 => c.len_utf16(),
+                PositionEncoding::Utf32 => 1,
+            };
+        }
+
+        line.len()
+    }
+}
+
+/// Convert the columns of the positions of a parse result into code units of the
+/// encoding (the parser counts grapheme clusters)
+///
+/// Afterwards, the `line_col` of the AST and of the diagnostics can be compared with
+/// LSP positions as they are.
+pub fn convert_file_result<ID>(
+    fr: &mut ParseFileResult<ID>,
+    content: &str,
+    encoding: PositionEncoding,
+) where
+    ID: Eq + std::hash::Hash + Clone + std::fmt::Debug,
+{
+    // All the units are identical for ASCII
+    if content.is_ascii() {
+        return;
+    }
+
+    let converter = Converter { content, encoding };
+
+    if let Some(ast) = &mut fr.ast {
+        converter.convert_aidl(ast);
+    }
+
+    for d in &mut fr.diagnostics {
+        converter.convert_diagnostic(d);
+    }
+}
+
+struct Converter<'a> {
+    content: &'a str,
+    encoding: PositionEncoding,
+}
+
+impl Converter<'_> {
+    fn convert_position(&self, p: &mut ast::Position) {
+        let offset = p.offset.min(self.content.len());
+        let line_start = self.content[..offset]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let column = match self.content.get(line_start..offset) {
+            Some(s) => self.encoding.count_units(s),
+            None => return, // not at a char boundary: keep the column of the parser
+        };
+
+        p.line_col.1 = column + 1;
+    }
+
+    fn convert_range(&self, r: &mut ast::Range) {
+        self.convert_position(&mut r.start);
+        self.convert_position(&mut r.end);
+    }
+
+    fn convert_diagnostic(&self, d: &mut diagnostic::Diagnostic) {
+        self.convert_range(&mut d.range);
+        for ri in &mut d.related_infos {
+            self.convert_range(&mut ri.range);
+        }
+    }
+
+    fn convert_aidl(&self, ast: &mut ast::Aidl) {
+        self.convert_range(&mut ast.package.symbol_range);
+        self.convert_range(&mut ast.package.full_range);
+
+        for import in ast.imports.iter_mut().chain(&mut ast.declared_parcelables) {
+            self.convert_range(&mut import.symbol_range);
+            self.convert_range(&mut import.full_range);
+        }
+
+        match &mut ast.item {
+            ast::Item::Interface(i) => {
+                self.convert_range(&mut i.symbol_range);
+                self.convert_range(&mut i.full_range);
+                for el in &mut i.elements {
+                    match el {
+                        ast::InterfaceElement::Const(c) => self.convert_const(c),
+                        ast::InterfaceElement::Method(m) => self.convert_method(m),
+                    }
+                }
+            }
+            ast::Item::Parcelable(p) => {
+                self.convert_range(&mut p.symbol_range);
+                self.convert_range(&mut p.full_range);
+                for f in &mut p.fields {
+                    self.convert_range(&mut f.symbol_range);
+                    self.convert_range(&mut f.full_range);
+                    self.convert_type(&mut f.field_type);
+                }
+            }
+            ast::Item::Enum(e) => {
+                self.convert_range(&mut e.symbol_range);
+                self.convert_range(&mut e.full_range);
+                for el in &mut e.elements {
+                    self.convert_range(&mut el.symbol_range);
+                    self.convert_range(&mut el.full_range);
+                }
+            }
+        }
+    }
+
+    fn convert_const(&self, c: &mut ast::Const) {
+        self.convert_range(&mut c.symbol_range);
+        self.convert_range(&mut c.full_range);
+        self.convert_type(&mut c.const_type);
+    }
+
+    fn convert_method(&self, m: &mut ast::Method) {
+        self.convert_range(&mut m.symbol_range);
+        self.convert_range(&mut m.full_range);
+        self.convert_range(&mut m.value_range);
+        self.convert_range(&mut m.oneway_range);
+        self.convert_type(&mut m.return_type);
+
+        for arg in &mut m.args {
+            self.convert_range(&mut arg.symbol_range);
+            self.convert_range(&mut arg.full_range);
+            self.convert_type(&mut arg.arg_type);
+            match &mut arg.direction {
+                ast::Direction::In(r) | ast::Direction::Out(r) | ast::Direction::InOut(r) => {
+                    self.convert_range(r)
+                }
+                ast::Direction::Unspecified => (),
+            }
+        }
+    }
+
+    fn convert_type(&self, t: &mut ast::Type) {
+        self.convert_range(&mut t.symbol_range);
+        self.convert_range(&mut t.full_range);
+        for generic_type in &mut t.generic_types {
+            self.convert_type(generic_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 grapheme cluster: 5 chars (3 emojis joined by ZWJ), 8 UTF-16 units, 18 bytes
+    const FAMILY: &str = "👨‍👩‍👧";
+
+    #[test]
+    fn count_units() {
+        for (s, utf8, utf16, utf32) in [
+            ("abc", 3, 3, 3),
+            ("é", 2, 1, 1),
+            ("e\u{301}", 3, 2, 2),
+            ("😀", 4, 2, 1),
+            (FAMILY, 18, 8, 5),
+        ] {
+            assert_eq!(PositionEncoding::Utf8.count_units(s), utf8, "{}", s);
+            assert_eq!(PositionEncoding::Utf16.count_units(s), utf16, "{}", s);
+            assert_eq!(PositionEncoding::Utf32.count_units(s), utf32, "{}", s);
+        }
+    }
+
+    #[test]
+    fn to_byte_offset() {
+        let line = "a😀é b";
+        assert_eq!(PositionEncoding::Utf16.to_byte_offset(line, 0), 0);
+        assert_eq!(PositionEncoding::Utf16.to_byte_offset(line, 1), 1);
+        assert_eq!(PositionEncoding::Utf16.to_byte_offset(line, 3), 5);
+        assert_eq!(PositionEncoding::Utf16.to_byte_offset(line, 4), 7);
+        assert_eq!(PositionEncoding::Utf32.to_byte_offset(line, 2), 5);
+        assert_eq!(PositionEncoding::Utf8.to_byte_offset(line, 5), 5);
+
+        // Inside a surrogate pair: the next char boundary
+        assert_eq!(PositionEncoding::Utf16.to_byte_offset(line, 2), 5);
+
+        // Beyond the end of the line
+        assert_eq!(
+            PositionEncoding::Utf16.to_byte_offset(line, 100),
+            line.len()
+        );
+    }
+
+    #[test]
+    fn negotiate() {
+        let negotiate = |encodings: serde_json::Value| {
+            PositionEncoding::negotiate(
+                &serde_json::json!({ "general": { "positionEncodings": encodings } }),
+            )
+        };
+
+        assert_eq!(
+            negotiate(serde_json::json!(["utf-16", "utf-8"])),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            negotiate(serde_json::json!(["utf-32", "utf-16"])),
+            PositionEncoding::Utf32
+        );
+        assert_eq!(
+            negotiate(serde_json::json!(["unknown", "utf-16"])),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(negotiate(serde_json::json!([])), PositionEncoding::Utf16);
+        assert_eq!(
+            PositionEncoding::negotiate(&serde_json::json!({})),
+            PositionEncoding::Utf16
+        );
+    }
+
+    #[test]
+    fn convert_position() {
+        let content = format!("ab\nc{}d\ne\u{301}😀f", FAMILY);
+        let column = |encoding, needle: &str| {
+            let offset = content.find(needle).unwrap();
+            let mut position = ast::Position {
+                offset,
+                line_col: (0, 0),
+            };
+            Converter {
+                content: &content,
+                encoding,
+            }
+            .convert_position(&mut position);
+            position.line_col.1
+        };
+
+        // Grapheme clusters count as their code units
+        assert_eq!(column(PositionEncoding::Utf8, "d"), 20);
+        assert_eq!(column(PositionEncoding::Utf16, "d"), 10);
+        assert_eq!(column(PositionEncoding::Utf32, "d"), 7);
+
+        // Combining characters and surrogate pairs
+        assert_eq!(column(PositionEncoding::Utf8, "f"), 8);
+        assert_eq!(column(PositionEncoding::Utf16, "f"), 5);
+        assert_eq!(column(PositionEncoding::Utf32, "f"), 4);
+    }
+
+    fn parse(content: &str, encoding: PositionEncoding) -> ast::Aidl {
+        let mut parser = aidl_parser::Parser::new();
+        parser.add_content(0, content);
+        let mut fr = parser.validate().remove(&0).unwrap();
+        convert_file_result(&mut fr, content, encoding);
+        fr.ast.unwrap()
+    }
+
+    fn get_const_column(ast: &ast::Aidl, name: &str) -> usize {
+        let interface = match &ast.item {
+            ast::Item::Interface(i) => i,
+            _ => panic!("not an interface"),
+        };
+        interface
+            .elements
+            .iter()
+            .find(|el| el.get_name() == name)
+            .unwrap()
+            .get_symbol_range()
+            .start
+            .line_col
+            .1
+    }
+
+    #[test]
+    fn non_ascii_doc_comment() {
+        let content = "package a;\n/** Größe — ça marche */\ninterface IFoo {\n    /** Größe */ const int G = 3;\n}\n";
+
+        for (encoding, column) in [
+            (PositionEncoding::Utf8, 30),
+            (PositionEncoding::Utf16, 28),
+            (PositionEncoding::Utf32, 28),
+        ] {
+            let ast = parse(content, encoding);
+            assert_eq!(get_const_column(&ast, "G"), column, "{:?}", encoding);
+
+            // The lines without non-ASCII characters are not changed
+            assert_eq!(ast.item.get_symbol_range().start.line_col, (3, 11));
+        }
+    }
+
+    #[test]
+    fn emoji_in_string_constant() {
+        let content = format!(
+            "package a;\ninterface IFoo {{\n    const String S = \"{}\"; const int N = 1;\n}}\n",
+            FAMILY
+        );
+
+        // The parser counts grapheme clusters
+        let mut parser = aidl_parser::Parser::new();
+        parser.add_content(0, &content);
+        let ast = parser.validate().remove(&0).unwrap().ast.unwrap();
+        assert_eq!(get_const_column(&ast, "N"), 37);
+
+        for (encoding, column) in [
+            (PositionEncoding::Utf8, 54),
+            (PositionEncoding::Utf16, 44),
+            (PositionEncoding::Utf32, 41),
+        ] {
+            let ast = parse(&content, encoding);
+            assert_eq!(get_const_column(&ast, "N"), column, "{:?}", encoding);
+            assert_eq!(get_const_column(&ast, "S"), 18, "{:?}", encoding);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use aidl_parser::ast;
+use aidl_parser::traverse::{self, SymbolFilter};
+use lsp_types::SelectionRange;
+
+use crate::position_encoding::PositionEncoding;
+use crate::utils;
+
+/// Expand-selection chain for the given position: from the innermost symbol range to
+/// its full range, then the enclosing symbols (e.g. method, item) and the whole file
+pub fn get_selection_range(
+    ast: &ast::Aidl,
+    content: &str,
+    position: &lsp_types::Position,
+    encoding: PositionEncoding,
+) -> SelectionRange {
+    let line_col = utils::from_lsp_position(position);
+
+    let mut ranges: Vec<ast::Range> = Vec::new();
+    traverse::walk_symbols(ast, SymbolFilter::All, |symbol| {
+        for range in [symbol.get_range(), symbol.get_full_range()] {
+            if contains(range, line_col) {
+                ranges.push(range.clone());
+            }
+        }
+    });
+
+    // Outermost (i.e. largest) first
+    ranges.sort_by_key(|r| {
+        (
+            std::cmp::Reverse(r.end.offset - r.start.offset),
+            r.start.offset,
+        )
+    });
+    ranges.dedup_by_key(|r| (r.start.offset, r.end.offset));
+
+    let file_range = lsp_types::Range {
+        start: lsp_types::Position::new(0, 0),
+        end: get_end_position(content, encoding),
+    };
+
+    let mut selection_range = SelectionRange {
+        range: file_range,
+        parent: None,
+    };
+
+    for range in ranges {
+        let range = utils::to_lsp_range(&range);
+        if range == selection_range.range {
+            continue;
+        }
+
+        selection_range = SelectionRange {
+            range,
+            parent: Some(Box::new(selection_range)),
+        };
+    }
+
+    selection_range
+}
+
+fn contains(range: &ast::Range, line_col: (usize, usize)) -> bool {
+    range.start.line_col <= line_col && line_col <= range.end.line_col
+}
+
+fn get_end_position(content: &str, encoding: PositionEncoding) -> lsp_types::Position {
+    let line = content.matches('\n').count();
+    let last_line = content.rsplit('\n').next().unwrap_or_default();
+
+    lsp_types::Position::new(line as u32, encoding.count_units(last_line) as u32)
+}
@@ -0,0 +1,516 @@
+use aidl_parser::{ast, traverse};
+use lsp_types::{
+    Documentation, ParameterInformation, ParameterLabel, SignatureHelp, SignatureInformation,
+};
+
+use crate::hover;
+use crate::state::GlobalState;
+use crate::utils;
+
+// Annotation parameter: name and type
+type AnnotationParam = (&'static str, &'static str);
+
+/// Known AIDL annotations: name, parameters and description
+const ANNOTATIONS: &[(&str, &[AnnotationParam], &str)] = &[
+    (
+        "Backing",
+        &[("type", "String")],
+        "Backing type of an enum: `\"byte\"`, `\"int\"` or `\"long\"`",
+    ),
+    (
+        "Descriptor",
+        &[("value", "String")],
+        "Descriptor of an interface (instead of its qualified name)",
+    ),
+    (
+        "EnforcePermission",
+        &[
+            ("value", "String"),
+            ("anyOf", "String[]"),
+            ("allOf", "String[]"),
+        ],
+        "Permission(s) enforced for an interface or a method",
+    ),
+    (
+        "JavaDerive",
+        &[("toString", "boolean"), ("equals", "boolean")],
+        "Generate `toString()` and/or `equals()` + `hashCode()` in Java",
+    ),
+    (
+        "JavaPassthrough",
+        &[("annotation", "String")],
+        "Annotation passed through to the generated Java code",
+    ),
+    (
+        "JavaSuppressLint",
+        &[("value", "String[]")],
+        "Lint warnings suppressed in the generated Java code",
+    ),
+    (
+        "RustDerive",
+        &[
+            ("Copy", "boolean"),
+            ("Clone", "boolean"),
+            ("PartialOrd", "boolean"),
+            ("Ord", "boolean"),
+            ("PartialEq", "boolean"),
+            ("Eq", "boolean"),
+            ("Hash", "boolean"),
+        ],
+        "Traits derived in the generated Rust code",
+    ),
+    (
+        "SuppressWarnings",
+        &[("value", "String[]")],
+        "Warnings suppressed by the AIDL compiler",
+    ),
+    (
+        "UnsupportedAppUsage",
+        &[
+            ("expectedSignature", "String"),
+            ("implicitMember", "String"),
+            ("maxTargetSdk", "int"),
+            ("publicAlternatives", "String"),
+            ("trackingBug", "long"),
+        ],
+        "Element used by apps although not part of the public SDK",
+    ),
+    ("nullable", &[("heap", "boolean")], "The value can be null"),
+];
+
+/// Signature help for the annotation, method declaration or constant declaration
+/// around the given position
+///
+/// The text is analyzed (instead of the AST) because the content is usually
+/// incomplete while typing.
+pub fn get_signature_help(
+    global_state: &GlobalState,
+    content: &str,
+    ast: Option<&ast::Aidl>,
+    position: &lsp_types::Position,
+) -> Option<SignatureHelp> {
+    let offset = utils::to_offset(content, position, global_state.position_encoding)?;
+    let text = &content[..offset];
+
+    if let Some(call) = find_open_call(text) {
+        if call.is_annotation {
+            return get_annotation_signature_help(&call);
+        }
+
+        return ast.and_then(|ast| get_method_signature_help(ast, &call));
+    }
+
+    get_const_signature_help(global_state, ast, text)
+}
+
+// An open parenthesis preceded by a name (e.g. `@JavaDerive(` or `myMethod(`)
+struct OpenCall<'a> {
+    name: &'a str,
+    is_annotation: bool,
+    comma_count: u32,
+    current_arg: &'a str,
+}
+
+fn find_open_call(text: &str) -> Option<OpenCall<'_>> {
+    // The comments and the strings are masked (e.g. `// f(` or `"a, b"`)
+    let masked = mask_comments_and_strings(text)?;
+
+    let mut depth = 0;
+    let mut angle_depth = 0;
+    let mut comma_count = 0;
+    let mut last_comma = None;
+
+    for (index, c) in masked.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth > 0 => depth -= 1,
+            '(' => {
+                let before = masked[..index].trim_end();
+                let name_start = before
+                    .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                let name = &text[name_start..before.len()];
+                if name.is_empty() {
+                    return None;
+                }
+
+                let arg_start = last_comma.unwrap_or(index) + 1;
+                return Some(OpenCall {
+                    name,
+                    is_annotation: before[..name_start].ends_with('@'),
+                    comma_count,
+                    current_arg: text[arg_start..].trim_start(),
+                });
+            }
+            '>' => angle_depth += 1,
+            '<' if angle_depth > 0 => angle_depth -= 1,
+            ',' if depth == 0 && angle_depth == 0 => {
+                if last_comma.is_none() {
+                    last_comma = Some(index);
+                }
+                comma_count += 1;
+            }
+            ';' | '{' | '}' if depth == 0 => return None,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+// Replace the comments and the content of the strings with spaces, keeping the offsets
+// and the line breaks (`None` if the text ends inside a comment)
+fn mask_comments_and_strings(text: &str) -> Option<String> {
+    enum State {
+        Code,
+        String,
+        LineComment,
+        BlockComment,
+    }
+
+    let mut masked = String::with_capacity(text.len());
+    let mut state = State::Code;
+    let mut chars = text.chars().peekable();
+    let mask = |masked: &mut String, c: char| match c {
+        '\n' => masked.push('\n'),
+        c => masked.push_str(&" ".repeat(c.len_utf8())),
+    };
+
+    while let Some(c) = chars.next() {
+        match state {
+            State::Code => match (c, chars.peek()) {
+                ('/', Some('/')) => {
+                    state = State::LineComment;
+                    mask(&mut masked, c);
+                }
+                ('/', Some('*')) => {
+                    chars.next();
+                    state = State::BlockComment;
+                    masked.push_str("  ");
+                }
+                ('"', _) => {
+                    state = State::String;
+                    masked.push(c);
+                }
+                _ => masked.push(c),
+            },
+            State::String => match c {
+                '"' | '\n' => {
+                    state = State::Code;
+                    masked.push(c);
+                }
+                '\\' => {
+                    mask(&mut masked, c);
+                    if let Some(escaped) = chars.next() {
+                        mask(&mut masked, escaped);
+                    }
+                }
+                _ => mask(&mut masked, c),
+            },
+            State::LineComment => {
+                if c == '\n' {
+                    state = State::Code;
+                }
+                mask(&mut masked, c);
+            }
+            State::BlockComment => {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    state = State::Code;
+                    masked.push_str("  ");
+                } else {
+                    mask(&mut masked, c);
+                }
+            }
+        }
+    }
+
+    match state {
+        State::LineComment | State::BlockComment => None,
+        State::Code | State::String => Some(masked),
+    }
+}
+
+fn get_annotation_signature_help(call: &OpenCall) -> Option<SignatureHelp> {
+    let (name, params, description) = ANNOTATIONS.iter().find(|(name, ..)| *name == call.name)?;
+
+    let param_strs: Vec<String> = params
+        .iter()
+        .map(|(param_name, param_type)| format!("{} {}", param_type, param_name))
+        .collect();
+
+    // Annotation parameters are named: the active parameter is given by the key
+    let current_key = call
+        .current_arg
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .trim();
+    let active_parameter = params
+        .iter()
+        .position(|(param_name, _)| *param_name == current_key)
+        .map(|i| i as u32)
+        .unwrap_or(call.comma_count);
+
+    Some(create_signature_help(
+        format!("@{}(", name),
+        &param_strs,
+        ")",
+        Some(description.to_string()),
+        active_parameter,
+    ))
+}
+
+fn get_method_signature_help(ast: &ast::Aidl, call: &OpenCall) -> Option<SignatureHelp> {
+    let mut method = None;
+    traverse::walk_methods(ast, |m| {
+        if m.name == call.name {
+            method = Some(m);
+        }
+    });
+    let method = method?;
+
+    let param_strs: Vec<String> = method
+        .args
+        .iter()
+        .map(|a| {
+            let direction = match a.direction {
+                ast::Direction::Unspecified => String::new(),
+                ref d => format!("{} ", d),
+            };
+            let name = a
+                .name
+                .as_ref()
+                .map(|n| format!(" {}", n))
+                .unwrap_or_default();
+            format!("{}{}{}", direction, utils::get_type_str(&a.arg_type), name)
+        })
+        .collect();
+
+    Some(create_signature_help(
+        format!(
+            "{} {}(",
+            utils::get_type_str(&method.return_type),
+            method.name
+        ),
+        &param_strs,
+        ")",
+        method.doc.as_deref().map(hover::doc_to_markdown),
+        call.comma_count,
+    ))
+}
+
+// e.g. `const int B = A + ` => signature of B + the referenced constants
+fn get_const_signature_help(
+    global_state: &GlobalState,
+    ast: Option<&ast::Aidl>,
+    text: &str,
+) -> Option<SignatureHelp> {
+    let statement_start = text.rfind([';', '{', '}'])? + 1;
+    let statement = text[statement_start..].trim_start();
+
+    // Skip the annotations
+    let mut statement = statement;
+    while statement.starts_with('@') {
+        let end = statement
+            .find(char::is_whitespace)
+            .unwrap_or(statement.len());
+        statement = statement[end..].trim_start();
+    }
+
+    let declaration = statement.strip_prefix("const")?;
+    let (declaration, expression) = declaration.split_once('=')?;
+    let (const_type, const_name) = declaration.trim().rsplit_once(char::is_whitespace)?;
+
+    // Describe the constants referenced in the expression
+    let references: Vec<String> = expression
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .filter(|word| word.chars().next().is_some_and(char::is_alphabetic))
+        .filter_map(|word| find_const(global_state, ast, word))
+        .map(|(name, c)| {
+            format!(
+                "- `{}`: `{}` = `{}`",
+                name,
+                utils::get_type_str(&c.const_type),
+                c.value
+            )
+        })
+        .collect();
+
+    Some(create_signature_help(
+        format!("const {} {} = ", const_type.trim(), const_name),
+        &[format!("<{} expression>", const_type.trim())],
+        ";",
+        if references.is_empty() {
+            None
+        } else {
+            Some(references.join("\n"))
+        },
+        0,
+    ))
+}
+
+// Find a constant of the current interface (e.g. `FOO`) or of another one (e.g. `IBar.FOO`)
+fn find_const<'a>(
+    global_state: &'a GlobalState,
+    ast: Option<&'a ast::Aidl>,
+    reference: &'a str,
+) -> Option<(&'a str, &'a ast::Const)> {
+    let (item_ast, const_name) = match reference.rsplit_once('.') {
+        Some((item_name, const_name)) => {
+            let key = match ast.and_then(|ast| {
+                ast.imports
+                    .iter()
+                    .find(|i| i.name == item_name || i.get_qualified_name() == item_name)
+            }) {
+                Some(import) => import.get_qualified_name(),
+                None => item_name.to_owned(),
+            };
+            (utils::get_item_ast(global_state, &key)?, const_name)
+        }
+        None => (ast?, reference),
+    };
+
+    let interface = item_ast.item.as_interface()?;
+    interface
+        .elements
+        .iter()
+        .find_map(|el| match el {
+            ast::InterfaceElement::Const(c) if c.name == const_name => Some(c),
+            _ => None,
+        })
+        .map(|c| (reference, c))
+}
+
+fn create_signature_help(
+    label_start: String,
+    params: &[String],
+    label_end: &str,
+    documentation: Option<String>,
+    active_parameter: u32,
+) -> SignatureHelp {
+    // Compute the offsets (in UTF-16 code units) of each parameter inside the label
+    let mut label = label_start;
+    let mut parameters = Vec::new();
+
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(param);
+        let end = label.encode_utf16().count() as u32;
+
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+
+    label.push_str(label_end);
+
+    SignatureHelp {
+        signatures: Vec::from([SignatureInformation {
+            label,
+            documentation: documentation.map(|d| {
+                Documentation::MarkupContent(lsp_types::MarkupContent {
+                    kind: lsp_types::MarkupKind::Markdown,
+                    value: d,
+                })
+            }),
+            parameters: Some(parameters),
+            active_parameter: Some(active_parameter),
+        }]),
+        active_signature: Some(0),
+        active_parameter: Some(active_parameter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Name, comma count and current argument of the open call at the end of the text
+    fn open_call(text: &str) -> Option<(&str, u32, &str)> {
+        find_open_call(text).map(|call| (call.name, call.comma_count, call.current_arg))
+    }
+
+    #[test]
+    fn open_calls() {
+        assert_eq!(open_call("void f("), Some(("f", 0, "")));
+        assert_eq!(
+            open_call("void f(int a, in List<int> b"),
+            Some(("f", 1, "in List<int> b"))
+        );
+        assert_eq!(open_call("void f(in Map<int, int> a, "), Some(("f", 1, "")));
+        assert_eq!(open_call("void f(int a);\nvoid g("), Some(("g", 0, "")));
+        assert_eq!(open_call("void f(int a) "), None);
+        assert_eq!(open_call("void f(int a);\n"), None);
+
+        let call = find_open_call("void f(int a);\n@JavaDerive(toString=true, ").unwrap();
+        assert_eq!(
+            (call.name, call.is_annotation, call.comma_count),
+            ("JavaDerive", true, 1)
+        );
+    }
+
+    #[test]
+    fn nested_calls() {
+        assert_eq!(
+            open_call("@JavaDerive(toString=f(1, 2), equals="),
+            Some(("JavaDerive", 1, "equals="))
+        );
+        assert_eq!(
+            open_call("void f(int a, @nullable(heap="),
+            Some(("nullable", 0, "heap="))
+        );
+        assert_eq!(
+            open_call("void f(int a, @nullable(heap=true) int[] b, "),
+            Some(("f", 2, ""))
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            open_call("void f(int a, // g(x, y\n    int b"),
+            Some(("f", 1, "// g(x, y\n    int b"))
+        );
+        assert_eq!(
+            open_call("void f(int a /* , */, int b"),
+            Some(("f", 1, "int b"))
+        );
+        assert_eq!(
+            open_call("void f(int a, /* ) */ int b"),
+            Some(("f", 1, "/* ) */ int b"))
+        );
+        assert_eq!(open_call("// void f(\nvoid g(int a, "), Some(("g", 1, "")));
+        assert_eq!(open_call("/* void f( */ void g("), Some(("g", 0, "")));
+
+        // Inside a comment
+        assert_eq!(open_call("void f(int a, // b, "), None);
+        assert_eq!(open_call("void f(int a, /* b, "), None);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            open_call("@Descriptor(value=\"a(b, c"),
+            Some(("Descriptor", 0, "value=\"a(b, c"))
+        );
+        assert_eq!(
+            open_call("@JavaPassthrough(annotation=\"@A(x, \\\"y\\\")\", "),
+            Some(("JavaPassthrough", 1, ""))
+        );
+        assert_eq!(
+            open_call("@UnsupportedAppUsage(expectedSignature=\"a//b\", implicitMember=\"c/*d\", "),
+            Some(("UnsupportedAppUsage", 2, ""))
+        );
+        assert_eq!(
+            open_call("const String S = \"f(\";\nvoid g(\"é\", "),
+            Some(("g", 1, ""))
+        );
+    }
+}
@@ -1,25 +1,155 @@
 use aidl_parser::{ast, ParseFileResult, Parser};
-use std::{collections::HashMap, path::PathBuf};
+use anyhow::Result;
+use lsp_types::notification::Notification;
+use lsp_types::Url;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::checks::ImportRoots;
+use crate::config::{Config, ConfigSources};
+use crate::excludes::Excludes;
+use crate::metrics::Metrics;
+use crate::position_encoding::PositionEncoding;
+
+/// Handler of the result of a request sent by the server
+pub type ResponseHandler = fn(&mut GlobalState, serde_json::Value) -> Result<()>;
 
 pub struct GlobalState {
     pub indexing_state: IndexingState,
     pub sender: crossbeam::channel::Sender<lsp_server::Message>,
     pub root_path: Option<PathBuf>,
-    pub parser: Parser<PathBuf>,
-    pub file_results: HashMap<PathBuf, ParseFileResult<PathBuf>>,
-    pub items_by_key: HashMap<ast::ItemKey, PathBuf>,
+    /// Resolved settings
+    pub config: Config,
+    pub config_sources: ConfigSources,
+    /// Compiled `excludes` of the settings
+    pub excludes: Excludes,
+    /// The client supports `workspace/configuration` requests
+    pub configuration_support: bool,
+    /// The client supports the dynamic registration of file watchers
+    pub watched_files_registration_support: bool,
+    pub parser: Parser<Url>,
+    /// Results of the files of the workspace
+    pub file_results: HashMap<Url, ParseFileResult<Url>>,
+    /// Results of the documents which are not files (e.g. `untitled:` or `git:`),
+    /// analyzed on their own against the workspace
+    pub virtual_documents: HashMap<Url, ParseFileResult<Url>>,
+    /// Contents of the files and of the virtual documents
+    pub file_contents: HashMap<Url, String>,
+    /// Documents opened in the client (their contents may differ from the files)
+    pub open_documents: HashSet<Url>,
+    pub items_by_key: HashMap<ast::ItemKey, Url>,
+    /// Kinds of the items of the workspace, on which the virtual documents depend
+    pub item_kinds: HashMap<ast::ItemKey, ast::ItemKind>,
+    /// Stubs of the items of the workspace (see `item_kinds`), against which a virtual
+    /// document is validated on its own
+    pub item_stubs_parser: Parser<Url>,
+    pub import_roots: ImportRoots,
+    pub workspace_diagnostics: HashMap<Url, Vec<lsp_types::Diagnostic>>,
+    /// The client pulls the diagnostics (`textDocument/diagnostic`) instead of
+    /// receiving them
+    pub pull_diagnostics: bool,
+    /// Unit of the columns of the LSP positions (the columns of the ASTs are converted
+    /// accordingly)
+    pub position_encoding: PositionEncoding,
+    pub diagnostics_refresh_support: bool,
+    /// `workspace/diagnostic` request waiting for a change, with the previous result IDs
+    pub pending_workspace_diagnostics:
+        Option<(lsp_server::RequestId, HashMap<lsp_types::Url, String>)>,
+    /// Requests sent to the client and waiting for a response (method and handler)
+    pub pending_requests: HashMap<lsp_server::RequestId, (&'static str, ResponseHandler)>,
+    pub metrics: Metrics,
+    next_request_id: i32,
 }
 
 impl GlobalState {
     pub fn new(sender: crossbeam::channel::Sender<lsp_server::Message>) -> Self {
+        let config = Config::default();
+        let excludes = Excludes::new(&config.excludes).expect("Invalid default excludes");
+
         GlobalState {
             indexing_state: IndexingState::Idle,
             sender,
             root_path: None,
+            config,
+            config_sources: ConfigSources::default(),
+            excludes,
+            configuration_support: false,
+            watched_files_registration_support: false,
             parser: Parser::new(),
             file_results: HashMap::new(),
+            virtual_documents: HashMap::new(),
+            file_contents: HashMap::new(),
+            open_documents: HashSet::new(),
             items_by_key: HashMap::new(),
+            item_kinds: HashMap::new(),
+            item_stubs_parser: Parser::new(),
+            import_roots: ImportRoots::default(),
+            workspace_diagnostics: HashMap::new(),
+            pull_diagnostics: false,
+            position_encoding: PositionEncoding::default(),
+            diagnostics_refresh_support: false,
+            pending_workspace_diagnostics: None,
+            pending_requests: HashMap::new(),
+            metrics: Metrics::default(),
+            next_request_id: 0,
+        }
+    }
+
+    /// Send a request from the server to the client, the result will be given to the
+    /// handler when the client responds
+    pub fn send_request<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+        handler: ResponseHandler,
+    ) {
+        let id = lsp_server::RequestId::from(self.next_request_id);
+        self.next_request_id += 1;
+        self.pending_requests
+            .insert(id.clone(), (R::METHOD, handler));
+
+        let request = lsp_server::Request::new(id, R::METHOD.to_owned(), params);
+        self.sender
+            .send(lsp_server::Message::Request(request))
+            .unwrap();
+    }
+
+    /// Whether a request of the given type has been sent and not answered yet
+    pub fn is_pending<R: lsp_types::request::Request>(&self) -> bool {
+        self.pending_requests
+            .values()
+            .any(|(method, _)| *method == R::METHOD)
+    }
+
+    /// Route the response of the client to the handler of the corresponding request
+    pub fn complete_request(&mut self, response: lsp_server::Response) -> Result<()> {
+        let (method, handler) = match self.pending_requests.remove(&response.id) {
+            Some(it) => it,
+            None => {
+                tracing::warn!("got response to unknown request: {:?}", response.id);
+                return Ok(());
+            }
+        };
+
+        if let Some(err) = response.error {
+            tracing::warn!("{} failed: {} (code: {})", method, err.message, err.code);
+            return Ok(());
         }
+
+        handler(self, response.result.unwrap_or_default())
+    }
+
+    /// Show a message to the user (`window/showMessage`)
+    pub fn show_message(&self, typ: lsp_types::MessageType, message: String) {
+        let notif = lsp_server::Notification::new(
+            lsp_types::notification::ShowMessage::METHOD.to_owned(),
+            lsp_types::ShowMessageParams { typ, message },
+        );
+
+        self.sender
+            .send(lsp_server::Message::Notification(notif))
+            .unwrap();
     }
 }
 
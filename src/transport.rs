@@ -0,0 +1,141 @@
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::thread;
+
+use anyhow::{Context, Result};
+use lsp_server::{Connection, Message};
+
+/// How the server communicates with the client
+#[derive(Debug)]
+pub enum Transport {
+    Stdio,
+    /// Wait for a client on the given address
+    Listen(Address),
+    /// Connect to a client listening on the given address
+    Connect(Address),
+}
+
+#[derive(Debug)]
+pub enum Address {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl FromStr for Address {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Ok(Address::Unix(path.into())),
+            #[cfg(not(unix))]
+            Some(_) => anyhow::bail!("Unix domain sockets are not supported on this platform"),
+            None => Ok(Address::Tcp(s.to_owned())),
+        }
+    }
+}
+
+/// Threads reading and writing the messages
+pub enum IoThreads {
+    Stdio(lsp_server::IoThreads),
+    Stream {
+        reader: thread::JoinHandle<io::Result<()>>,
+        writer: thread::JoinHandle<io::Result<()>>,
+    },
+}
+
+impl IoThreads {
+    pub fn join(self) -> io::Result<()> {
+        match self {
+            IoThreads::Stdio(io_threads) => io_threads.join(),
+            IoThreads::Stream { reader, writer } => {
+                join_thread(reader)?;
+                join_thread(writer)
+            }
+        }
+    }
+}
+
+fn join_thread(thread: thread::JoinHandle<io::Result<()>>) -> io::Result<()> {
+    thread
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("IO thread panicked")))
+}
+
+/// Create the connection with the client
+pub fn create_connection(transport: &Transport) -> Result<(Connection, IoThreads)> {
+    match transport {
+        Transport::Stdio => {
+            let (connection, io_threads) = Connection::stdio();
+            Ok((connection, IoThreads::Stdio(io_threads)))
+        }
+        Transport::Listen(Address::Tcp(address)) => {
+            let listener = TcpListener::bind(address)
+                .with_context(|| format!("Could not listen on {}", address))?;
+            tracing::info!("Waiting for a client on {}", address);
+            let (stream, client_address) = listener.accept()?;
+            tracing::info!("Client connected from {}", client_address);
+            Ok(stream_connection(stream.try_clone()?, stream))
+        }
+        Transport::Connect(Address::Tcp(address)) => {
+            let stream = TcpStream::connect(address)
+                .with_context(|| format!("Could not connect to {}", address))?;
+            Ok(stream_connection(stream.try_clone()?, stream))
+        }
+        #[cfg(unix)]
+        Transport::Listen(Address::Unix(path)) => {
+            let listener = std::os::unix::net::UnixListener::bind(path)
+                .with_context(|| format!("Could not listen on {:?}", path))?;
+            tracing::info!("Waiting for a client on {:?}", path);
+            let (stream, _) = listener.accept()?;
+            tracing::info!("Client connected");
+
+            // The socket file is not needed anymore once the client is connected
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!("Could not remove {:?}: {}", path, e);
+            }
+
+            Ok(stream_connection(stream.try_clone()?, stream))
+        }
+        #[cfg(unix)]
+        Transport::Connect(Address::Unix(path)) => {
+            let stream = std::os::unix::net::UnixStream::connect(path)
+                .with_context(|| format!("Could not connect to {:?}", path))?;
+            Ok(stream_connection(stream.try_clone()?, stream))
+        }
+    }
+}
+
+// Connection over a (TCP or Unix) stream, with one thread reading and one writing
+fn stream_connection<S>(read_stream: S, mut write_stream: S) -> (Connection, IoThreads)
+where
+    S: Read + Write + Send + 'static,
+{
+    let (reader_sender, reader_receiver) = crossbeam::channel::bounded::<Message>(0);
+    let reader = thread::spawn(move || {
+        let mut buf_read = BufReader::new(read_stream);
+        while let Some(msg) = Message::read(&mut buf_read)? {
+            let is_exit = matches!(&msg, Message::Notification(n) if n.method == "exit");
+            if reader_sender.send(msg).is_err() || is_exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let (writer_sender, writer_receiver) = crossbeam::channel::bounded::<Message>(0);
+    let writer = thread::spawn(move || {
+        writer_receiver
+            .into_iter()
+            .try_for_each(|msg| msg.write(&mut write_stream))
+    });
+
+    let connection = Connection {
+        sender: writer_sender,
+        receiver: reader_receiver,
+    };
+
+    (connection, IoThreads::Stream { reader, writer })
+}
@@ -0,0 +1,112 @@
+//! Type hierarchy based on containment (AIDL has no inheritance): the "supertypes" of
+//! an item are the types it embeds (as field, argument or return type) and its
+//! "subtypes" are the items embedding it
+
+use std::collections::BTreeSet;
+
+use aidl_parser::ast;
+use aidl_parser::symbol::Symbol;
+use aidl_parser::traverse::{self, SymbolFilter};
+
+use crate::lsp_ext::TypeHierarchyItem;
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Item declared or referenced (as type or import) at the given position
+pub fn prepare(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    position: &lsp_types::Position,
+) -> Option<TypeHierarchyItem> {
+    let key = match traverse::find_symbol_at_line_col(
+        ast,
+        SymbolFilter::All,
+        utils::from_lsp_position(position),
+    )? {
+        Symbol::Type(ast::Type {
+            kind: ast::TypeKind::Resolved(key, _),
+            ..
+        }) => key.clone(),
+        Symbol::Import(i) => i.get_qualified_name(),
+        Symbol::Interface(..) | Symbol::Parcelable(..) | Symbol::Enum(..) => ast.get_key(),
+        _ => return None,
+    };
+
+    create_item(global_state, &key)
+}
+
+/// Items embedded by the given item
+pub fn get_supertypes(
+    global_state: &GlobalState,
+    item: &TypeHierarchyItem,
+) -> Vec<TypeHierarchyItem> {
+    let key = match get_item_key(item) {
+        Some(k) => k,
+        None => return Vec::new(),
+    };
+
+    let ast = match utils::get_item_ast(global_state, &key) {
+        Some(ast) => ast,
+        None => return Vec::new(),
+    };
+
+    get_embedded_keys(ast)
+        .iter()
+        .filter_map(|k| create_item(global_state, k))
+        .collect()
+}
+
+/// Items embedding the given item
+pub fn get_subtypes(
+    global_state: &GlobalState,
+    item: &TypeHierarchyItem,
+) -> Vec<TypeHierarchyItem> {
+    let key = match get_item_key(item) {
+        Some(k) => k,
+        None => return Vec::new(),
+    };
+
+    let embedding_keys: BTreeSet<&ast::ItemKey> = global_state
+        .items_by_key
+        .keys()
+        .filter(|other_key| {
+            utils::get_item_ast(global_state, other_key)
+                .map(|ast| get_embedded_keys(ast).contains(&key))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    embedding_keys
+        .into_iter()
+        .filter_map(|k| create_item(global_state, k))
+        .collect()
+}
+
+// Resolved types of the item (sorted, without the item itself)
+fn get_embedded_keys(ast: &ast::Aidl) -> BTreeSet<ast::ItemKey> {
+    let own_key = ast.get_key();
+
+    utils::get_resolved_type_keys(ast)
+        .into_iter()
+        .filter(|k| *k != own_key)
+        .collect()
+}
+
+fn get_item_key(item: &TypeHierarchyItem) -> Option<ast::ItemKey> {
+    item.data.as_ref()?.as_str().map(str::to_owned)
+}
+
+fn create_item(global_state: &GlobalState, key: ast::ItemKeyRef) -> Option<TypeHierarchyItem> {
+    let uri = global_state.items_by_key.get(key)?;
+    let ast = utils::get_item_ast(global_state, key)?;
+
+    Some(TypeHierarchyItem {
+        name: ast.item.get_name().to_owned(),
+        kind: utils::to_lsp_symbol_kind(&utils::get_item_symbol(ast))?,
+        detail: Some(ast.package.name.clone()),
+        uri: uri.clone(),
+        range: utils::to_lsp_range(ast.item.get_full_range()),
+        selection_range: utils::to_lsp_range(ast.item.get_symbol_range()),
+        data: Some(serde_json::Value::String(key.to_owned())),
+    })
+}
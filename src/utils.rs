@@ -1,9 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-use aidl_parser::{ast, symbol::Symbol};
+use aidl_parser::{ast, symbol::Symbol, traverse};
 use anyhow::Result;
 
+use crate::error::LspError;
+use crate::position_encoding::PositionEncoding;
 use crate::state::GlobalState;
+use crate::values;
 
 pub fn to_lsp_range(r: &ast::Range) -> lsp_types::Range {
     lsp_types::Range {
@@ -12,6 +16,25 @@ pub fn to_lsp_range(r: &ast::Range) -> lsp_types::Range {
     }
 }
 
+// Range covering the whole lines of the given range (incl. line break)
+pub fn to_lsp_line_range(r: &ast::Range) -> lsp_types::Range {
+    lsp_types::Range {
+        start: lsp_types::Position {
+            line: r.start.line_col.0 as u32 - 1,
+            character: 0,
+        },
+        end: lsp_types::Position {
+            line: r.end.line_col.0 as u32,
+            character: 0,
+        },
+    }
+}
+
+// Check if 2 LSP ranges overlap (including their boundaries)
+pub fn ranges_overlap(a: &lsp_types::Range, b: &lsp_types::Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
 // Convert 1-based line_col into LSP 0-based Position
 fn to_lsp_position(p: &ast::Position) -> lsp_types::Position {
     lsp_types::Position {
@@ -20,11 +43,91 @@ fn to_lsp_position(p: &ast::Position) -> lsp_types::Position {
     }
 }
 
+// Convert LSP 0-based Position into a byte offset inside the content
+pub fn to_offset(
+    content: &str,
+    p: &lsp_types::Position,
+    encoding: PositionEncoding,
+) -> Option<usize> {
+    let line_start = if p.line == 0 {
+        0
+    } else {
+        content.match_indices('\n').nth(p.line as usize - 1)?.0 + 1
+    };
+
+    let line = &content[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let column = encoding.to_byte_offset(line, p.character as usize);
+
+    Some(line_start + column)
+}
+
+// Convert a byte offset inside the content into an LSP 0-based Position
+pub fn offset_to_lsp_position(
+    content: &str,
+    offset: usize,
+    encoding: PositionEncoding,
+) -> lsp_types::Position {
+    let before = &content[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    lsp_types::Position {
+        line: before.matches('\n').count() as u32,
+        character: encoding.count_units(&before[line_start..]) as u32,
+    }
+}
+
 // Convert 0-based Position into 1-based line_col
 pub fn from_lsp_position(p: &lsp_types::Position) -> (usize, usize) {
     (p.line as usize + 1, p.character as usize + 1)
 }
 
+/// The AST of the file defining the item with the given key
+pub fn get_item_ast<'a>(
+    global_state: &'a GlobalState,
+    item_key: ast::ItemKeyRef,
+) -> Option<&'a ast::Aidl> {
+    global_state
+        .items_by_key
+        .get(item_key)
+        .and_then(|uri| global_state.file_results.get(uri))
+        .and_then(|fr| fr.ast.as_ref())
+}
+
+/// The symbol of the (top-level) item of an AST
+pub fn get_item_symbol(ast: &ast::Aidl) -> Symbol<'_> {
+    match &ast.item {
+        ast::Item::Interface(i) => Symbol::Interface(i, &ast.package),
+        ast::Item::Parcelable(p) => Symbol::Parcelable(p, &ast.package),
+        ast::Item::Enum(e) => Symbol::Enum(e, &ast.package),
+    }
+}
+
+/// Same as `Symbol::get_qualified_name()` but with a separator between the package and
+/// the name of enums
+pub fn get_qualified_name(symbol: &Symbol) -> Option<String> {
+    match symbol {
+        Symbol::Enum(e, pkg) => Some(format!("{}.{}", pkg.name, e.name)),
+        _ => symbol.get_qualified_name(),
+    }
+}
+
+/// Type as written in AIDL (e.g. `List<String>` or `int[]`)
+pub fn get_type_str(t: &ast::Type) -> String {
+    let generic_types = t
+        .generic_types
+        .iter()
+        .map(get_type_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match t.kind {
+        ast::TypeKind::Array => format!("{}[]", generic_types),
+        _ if generic_types.is_empty() => t.name.clone(),
+        _ => format!("{}<{}>", t.name, generic_types),
+    }
+}
+
 pub fn get_target_link(
     global_state: &GlobalState,
     origin_range: &ast::Range,
@@ -33,19 +136,11 @@ pub fn get_target_link(
     global_state
         .items_by_key
         .get(target_item_key)
-        .map(|target_uri| global_state.file_results.get(target_uri))
-        .flatten()
-        .map(|fr| fr.ast.as_ref().map(|ast| (&fr.id, ast)))
-        .flatten()
-        .map(|(path, ast)| {
-            lsp_types::Url::from_file_path(path)
-                .ok()
-                .map(|uri| (uri, ast))
-        })
-        .flatten()
+        .and_then(|target_uri| global_state.file_results.get(target_uri))
+        .and_then(|fr| fr.ast.as_ref().map(|ast| (&fr.id, ast)))
         .map(|(uri, ast)| lsp_types::LocationLink {
             origin_selection_range: Some(to_lsp_range(origin_range)),
-            target_uri: uri,
+            target_uri: uri.clone(),
             target_range: to_lsp_range(ast.item.get_full_range()),
             target_selection_range: to_lsp_range(ast.item.get_symbol_range()),
         })
@@ -55,15 +150,8 @@ pub fn to_lsp_symbol_info(
     symbol: &Symbol,
     uri: lsp_types::Url,
 ) -> Option<lsp_types::SymbolInformation> {
-    let kind = match to_lsp_symbol_kind(symbol) {
-        Some(k) => k,
-        None => return None,
-    };
-
-    let name = match symbol.get_name() {
-        Some(n) => n,
-        None => return None,
-    };
+    let kind = to_lsp_symbol_kind(symbol)?;
+    let name = symbol.get_name()?;
 
     let location = lsp_types::Location {
         uri,
@@ -82,15 +170,8 @@ pub fn to_lsp_symbol_info(
 }
 
 pub fn to_lsp_doc_symbol(symbol: &Symbol) -> Option<lsp_types::DocumentSymbol> {
-    let kind = match to_lsp_symbol_kind(symbol) {
-        Some(k) => k,
-        None => return None,
-    };
-
-    let name = match symbol.get_name() {
-        Some(n) => n,
-        None => return None,
-    };
+    let kind = to_lsp_symbol_kind(symbol)?;
+    let name = symbol.get_name()?;
 
     #[allow(deprecated)]
     Some(lsp_types::DocumentSymbol {
@@ -105,7 +186,23 @@ pub fn to_lsp_doc_symbol(symbol: &Symbol) -> Option<lsp_types::DocumentSymbol> {
     })
 }
 
-fn to_lsp_symbol_kind(symbol: &Symbol) -> Option<lsp_types::SymbolKind> {
+/// Insert a document symbol into the tree, as a child of the (last) symbol containing it
+pub fn insert_doc_symbol(
+    symbols: &mut Vec<lsp_types::DocumentSymbol>,
+    doc_symbol: lsp_types::DocumentSymbol,
+) {
+    let parent = symbols
+        .iter_mut()
+        .rev()
+        .find(|s| s.range.start <= doc_symbol.range.start && doc_symbol.range.end <= s.range.end);
+
+    match parent {
+        Some(parent) => insert_doc_symbol(parent.children.get_or_insert_with(Vec::new), doc_symbol),
+        None => symbols.push(doc_symbol),
+    }
+}
+
+pub fn to_lsp_symbol_kind(symbol: &Symbol) -> Option<lsp_types::SymbolKind> {
     Some(match symbol {
         Symbol::Package(..) => lsp_types::SymbolKind::PACKAGE,
         Symbol::Import(..) => lsp_types::SymbolKind::PACKAGE,
@@ -113,7 +210,7 @@ fn to_lsp_symbol_kind(symbol: &Symbol) -> Option<lsp_types::SymbolKind> {
         Symbol::Parcelable(..) => lsp_types::SymbolKind::STRUCT,
         Symbol::Enum(..) => lsp_types::SymbolKind::ENUM,
         Symbol::Method(..) => lsp_types::SymbolKind::METHOD,
-        Symbol::Arg(..) => return None,
+        Symbol::Arg(..) => lsp_types::SymbolKind::VARIABLE,
         Symbol::Const(..) => lsp_types::SymbolKind::CONSTANT,
         Symbol::Field(..) => lsp_types::SymbolKind::FIELD,
         Symbol::EnumElement(..) => lsp_types::SymbolKind::ENUM_MEMBER,
@@ -121,34 +218,233 @@ fn to_lsp_symbol_kind(symbol: &Symbol) -> Option<lsp_types::SymbolKind> {
     })
 }
 
+/// Results of a file of the workspace or of a virtual document
 pub fn get_file_results<'a>(
     global_state: &'a GlobalState,
-    path: &Path,
-) -> Result<&'a aidl_parser::ParseFileResult<PathBuf>> {
+    uri: &lsp_types::Url,
+) -> Result<&'a aidl_parser::ParseFileResult<lsp_types::Url>> {
     let fr = global_state
         .file_results
-        .get(path)
-        .ok_or_else(|| -> anyhow::Error {
-            anyhow::anyhow!("File not found: `{}`", path.to_string_lossy())
-        })?;
+        .get(uri)
+        .or_else(|| global_state.virtual_documents.get(uri))
+        .ok_or_else(|| LspError::unknown_document(uri))?;
 
     Ok(fr)
 }
 
-pub fn uri_to_path(uri: &lsp_types::Url) -> Result<PathBuf> {
-    let path = uri
-        .to_file_path()
-        .map_err(|_| anyhow::anyhow!("Invalid path: {}", uri.path()))?;
+/// The URI under which a document is stored: canonical path for files, unchanged for
+/// virtual documents (see [`is_virtual_document`])
+pub fn normalize_uri(uri: &lsp_types::Url) -> lsp_types::Url {
+    let path = match uri.to_file_path() {
+        Ok(path) => path,
+        Err(_) => return uri.clone(),
+    };
 
     // As std::fs::canonicalize() leads to some issues on Windows because it returns UNC
     // paths which are not properly handled, we use another version (dunce)
     // (see https://lib.rs/crates/dunce for more info)
     let path = dunce::canonicalize(path.clone()).unwrap_or(path);
 
-    Ok(path)
+    lsp_types::Url::from_file_path(path).unwrap_or_else(|_| uri.clone())
+}
+
+/// Documents which are not files on disk (e.g. `untitled:` buffers or `git:` diff views)
+pub fn is_virtual_document(uri: &lsp_types::Url) -> bool {
+    uri.scheme() != "file"
 }
 
 pub fn path_to_uri(path: &Path) -> Result<lsp_types::Url> {
     lsp_types::Url::from_file_path(path)
         .map_err(|_| anyhow::anyhow!("Invalid path: {}", path.to_string_lossy()))
 }
+
+/// Key of the only item of the workspace with the given (simple) name, if any
+pub fn find_unique_item_key<'a>(
+    global_state: &'a GlobalState,
+    name: &str,
+) -> Option<&'a ast::ItemKey> {
+    let suffix = format!(".{}", name);
+    let mut candidates = global_state
+        .items_by_key
+        .keys()
+        .filter(|key| key.ends_with(&suffix));
+
+    match (candidates.next(), candidates.next()) {
+        (Some(key), None) => Some(key),
+        _ => None,
+    }
+}
+
+/// Key of the item referenced by a name in a file: imported, qualified or of the same
+/// package (`None` if there is no such item in the workspace)
+pub fn resolve_item_key(
+    global_state: &GlobalState,
+    ast: &ast::Aidl,
+    name: &str,
+) -> Option<ast::ItemKey> {
+    if let Some(import) = ast.imports.iter().find(|i| i.name == name) {
+        return Some(import.get_qualified_name());
+    }
+
+    [name.to_owned(), format!("{}.{}", ast.package.name, name)]
+        .into_iter()
+        .find(|key| global_state.items_by_key.contains_key(key))
+}
+
+/// Qualified names of all the resolved types referenced in the AST
+pub fn get_resolved_type_keys(ast: &ast::Aidl) -> HashSet<ast::ItemKey> {
+    let mut keys = HashSet::new();
+    traverse::walk_types(ast, |t| {
+        if let ast::TypeKind::Resolved(key, _) = &t.kind {
+            keys.insert(key.clone());
+        }
+    });
+
+    keys
+}
+
+/// Text of the whole lines covered by the given range, incl. line breaks
+/// (the range must start and end at the beginning of a line)
+pub fn get_lines(content: &str, range: &lsp_types::Range) -> Option<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let start = range.start.line as usize;
+    let end = range.end.line as usize;
+
+    if start > end || end > lines.len() {
+        return None;
+    }
+
+    Some(lines[start..end].concat())
+}
+
+/// Text edit which inserts an import, keeping the imports in alphabetical order
+pub fn get_add_import_edit(ast: &ast::Aidl, key: ast::ItemKeyRef) -> lsp_types::TextEdit {
+    let next_import = ast
+        .imports
+        .iter()
+        .find(|i| i.get_qualified_name().as_str() > key);
+
+    let (line, new_text) = match (next_import, ast.imports.last()) {
+        (Some(next), _) => (
+            to_lsp_range(&next.full_range).start.line,
+            format!("import {};\n", key),
+        ),
+        (None, Some(last)) => (
+            to_lsp_range(&last.full_range).end.line + 1,
+            format!("import {};\n", key),
+        ),
+        (None, None) => (
+            to_lsp_range(&ast.package.full_range).end.line + 1,
+            format!("\nimport {};\n", key),
+        ),
+    };
+
+    let pos = lsp_types::Position { line, character: 0 };
+    lsp_types::TextEdit {
+        range: lsp_types::Range {
+            start: pos,
+            end: pos,
+        },
+        new_text,
+    }
+}
+
+/// Workspace edit which renames (or moves) a file
+pub fn get_rename_file_edit(
+    old_uri: &lsp_types::Url,
+    new_uri: &lsp_types::Url,
+) -> lsp_types::WorkspaceEdit {
+    lsp_types::WorkspaceEdit {
+        document_changes: Some(lsp_types::DocumentChanges::Operations(Vec::from([
+            lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Rename(
+                lsp_types::RenameFile {
+                    old_uri: old_uri.clone(),
+                    new_uri: new_uri.clone(),
+                    options: None,
+                    annotation_id: None,
+                },
+            )),
+        ]))),
+        ..lsp_types::WorkspaceEdit::default()
+    }
+}
+
+/// Annotation as written in AIDL (e.g. `@Backing(type="byte")`)
+pub fn get_annotation_str(annotation: &ast::Annotation) -> String {
+    if annotation.key_values.is_empty() {
+        return annotation.name.clone();
+    }
+
+    let mut key_values: Vec<String> = annotation
+        .key_values
+        .iter()
+        .map(|(k, v)| match v {
+            Some(v) => format!("{}={}", k, v),
+            None => k.clone(),
+        })
+        .collect();
+    key_values.sort();
+
+    format!("{}({})", annotation.name, key_values.join(", "))
+}
+
+/// Backing type of an enum, given via `@Backing(type="...")` (`byte` by default)
+pub fn get_enum_backing_type(e: &ast::Enum) -> String {
+    e.annotations
+        .iter()
+        .filter(|a| a.name == "@Backing")
+        .find_map(|a| a.key_values.get("type").cloned().flatten())
+        .map(|t| t.trim_matches('"').to_owned())
+        .unwrap_or_else(|| String::from("byte"))
+}
+
+/// Values of the enum elements: explicit or implicit (previous value + 1, starting at 0),
+/// in the range of the backing type (e.g. `0xFF` is -1 for a `byte` enum)
+///
+/// The values are evaluated from the content as they may be expressions referencing the
+/// other elements (e.g. `B = A << 1`), the value is `None` if it cannot be evaluated.
+pub fn get_enum_element_values(e: &ast::Enum, content: &str) -> Vec<Option<i64>> {
+    let backing_type = get_enum_backing_type(e);
+
+    let mut values_by_name: HashMap<&str, Option<i64>> = HashMap::new();
+    let mut previous: Option<i64> = Some(-1);
+    for declaration in values::get_enum_declarations(e, content) {
+        let value = match &declaration.value {
+            Some(tokens) => values::evaluate(tokens, &|t: &values::Token| {
+                let name = values::get_referenced_name(t, &e.name)?;
+                values_by_name.get(name.text).copied().flatten()
+            }),
+            None => previous.and_then(|p| p.checked_add(1)),
+        }
+        .map(|v| values::wrap_to_type(v, &backing_type));
+
+        values_by_name.insert(declaration.name.text, value);
+        previous = value;
+    }
+
+    e.elements
+        .iter()
+        .map(|el| values_by_name.get(el.name.as_str()).copied().flatten())
+        .collect()
+}
+
+/// Parse an integer literal (decimal or hexadecimal, with optional sign and `l` suffix)
+pub fn parse_int_literal(s: &str) -> Option<i64> {
+    let s = s.trim().trim_end_matches(['l', 'L']);
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    // Hexadecimal literals are given in two's complement (e.g. `0xFFFFFFFFFFFFFFFF`)
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => s.parse::<i64>().ok()?,
+    };
+
+    if negative {
+        value.checked_neg()
+    } else {
+        Some(value)
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+
+use aidl_parser::ast;
+
+use crate::utils;
+
+/// Token of a value, with its byte offset in the content (identifiers include their
+/// qualifier, e.g. `IFoo.A`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub offset: usize,
+}
+
+impl Token<'_> {
+    pub fn is_identifier(&self) -> bool {
+        self.text
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+}
+
+/// Declaration of a constant, enum element or field with its value, e.g. `B = A + 1`
+/// (`None` for the enum elements with an implicit value)
+#[derive(Debug)]
+pub struct Declaration<'a> {
+    /// Type of the constants and fields (`None` for the enum elements or if it is not a
+    /// simple name, e.g. `int[]`)
+    pub type_name: Option<Token<'a>>,
+    pub name: Token<'a>,
+    pub value: Option<Vec<Token<'a>>>,
+}
+
+/// Declarations of the body of an item, in order
+///
+/// They are read from the content because the parser only supports literal values:
+/// the declarations whose value is an expression (e.g. `const int B = 1 << A;`) are not
+/// part of the AST.
+pub fn get_declarations<'a>(item: &ast::Item, content: &'a str) -> Vec<Declaration<'a>> {
+    let is_enum = matches!(item, ast::Item::Enum(_));
+    get_body_declarations(
+        content,
+        item.get_symbol_range(),
+        item.get_full_range(),
+        is_enum,
+    )
+}
+
+/// Declarations of the elements of an enum, in order (see `get_declarations()`)
+pub fn get_enum_declarations<'a>(e: &ast::Enum, content: &'a str) -> Vec<Declaration<'a>> {
+    get_body_declarations(content, &e.symbol_range, &e.full_range, true)
+}
+
+fn get_body_declarations<'a>(
+    content: &'a str,
+    symbol_range: &ast::Range,
+    full_range: &ast::Range,
+    is_enum: bool,
+) -> Vec<Declaration<'a>> {
+    let start = symbol_range.end.offset;
+    let end = full_range.end.offset.min(content.len());
+    let body_start = match content.get(start..end).and_then(|s| s.find('{')) {
+        Some(i) => start + i + 1,
+        None => return Vec::new(),
+    };
+
+    let separator = if is_enum { "," } else { ";" };
+
+    // Split the statements (or enum elements) of the body
+    let mut statements: Vec<Vec<Token>> = Vec::from([Vec::new()]);
+    let mut depth = 0;
+    for token in tokenize(&content[body_start..end], body_start) {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" => depth -= 1,
+            "}" if depth == 0 => break,
+            "}" => depth -= 1,
+            t if t == separator && depth == 0 => {
+                statements.push(Vec::new());
+                continue;
+            }
+            _ => (),
+        }
+
+        if let Some(statement) = statements.last_mut() {
+            statement.push(token);
+        }
+    }
+
+    statements
+        .into_iter()
+        .filter_map(|tokens| {
+            // The `=` of the annotation parameters is nested in parentheses
+            let mut depth = 0;
+            let assignment = tokens.iter().position(|t| {
+                match t.text {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => depth -= 1,
+                    _ => (),
+                }
+                depth == 0 && t.text == "="
+            });
+
+            match assignment {
+                Some(index) => {
+                    let name = tokens[..index].last().filter(|t| t.is_identifier())?;
+                    let type_name = tokens[..index]
+                        .iter()
+                        .rev()
+                        .nth(1)
+                        .filter(|t| t.is_identifier() && !is_enum);
+                    Some(Declaration {
+                        type_name: type_name.copied(),
+                        name: *name,
+                        value: Some(tokens[index + 1..].to_vec()),
+                    })
+                }
+                None if is_enum => {
+                    let name = tokens.last().filter(|t| t.is_identifier())?;
+                    Some(Declaration {
+                        type_name: None,
+                        name: *name,
+                        value: None,
+                    })
+                }
+                None => None,
+            }
+        })
+        .collect()
+}
+
+/// Tokens of a source (the comments are skipped), with their offsets shifted by the
+/// given offset
+pub fn tokenize(source: &str, offset: usize) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        } else if rest.starts_with("//") {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+            continue;
+        } else if rest.starts_with("/*") {
+            rest = &rest[rest[2..].find("*/").map_or(rest.len(), |i| i + 4)..];
+            continue;
+        } else if c == '"' || c == '\'' {
+            // String or char literal (up to the end of the line if not terminated)
+            match rest[1..].find([c, '\n']) {
+                Some(i) if rest[1 + i..].starts_with(c) => i + 2,
+                Some(i) => i + 1,
+                None => rest.len(),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '@' {
+            // Identifier (or annotation) with its qualifier
+            let mut len = c.len_utf8();
+            loop {
+                let ident_len = rest[len..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len() - len);
+                len += ident_len;
+                let next = &rest[len..];
+                if next.starts_with('.')
+                    && next[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                {
+                    len += 1;
+                } else {
+                    break len;
+                }
+            }
+        } else if c.is_ascii_digit() {
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len())
+        } else if ["<<", ">>", "<=", ">=", "==", "!=", "&&", "||"]
+            .iter()
+            .any(|op| rest.starts_with(op))
+        {
+            2
+        } else {
+            c.len_utf8()
+        };
+
+        tokens.push(Token {
+            text: &rest[..len],
+            offset: offset + source.len() - rest.len(),
+        });
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+/// The name referenced by an identifier of a value if it is an element of the given
+/// item (e.g. `A`, `IFoo.A` or `com.foo.IFoo.A`), with its offset
+pub fn get_referenced_name<'a>(token: &Token<'a>, item_name: &str) -> Option<Token<'a>> {
+    if !token.is_identifier() {
+        return None;
+    }
+
+    let (qualifier, name) = match token.text.rsplit_once('.') {
+        Some((qualifier, name)) => (Some(qualifier), name),
+        None => (None, token.text),
+    };
+
+    match qualifier {
+        Some(q) if q != item_name && !q.ends_with(&format!(".{}", item_name)) => None,
+        _ => Some(Token {
+            text: name,
+            offset: token.offset + token.text.len() - name.len(),
+        }),
+    }
+}
+
+/// Evaluate an integer expression: literals, references (resolved by `lookup`),
+/// parentheses and the arithmetic and bitwise operators (`None` if it is not a valid
+/// integer expression or if it overflows)
+pub fn evaluate(tokens: &[Token], lookup: &dyn Fn(&Token) -> Option<i64>) -> Option<i64> {
+    let mut evaluator = Evaluator {
+        tokens,
+        position: 0,
+        lookup,
+    };
+
+    let value = evaluator.evaluate_binary(0)?;
+    if evaluator.position != tokens.len() {
+        return None;
+    }
+
+    Some(value)
+}
+
+/// Values of the integer constants of an interface, by name, wrapped to their type
+///
+/// The values may reference the other constants of the interface, declared before or
+/// after (e.g. `const int B = 1 << A;`), or the constants of other items (e.g.
+/// `IBar.FOO`, resolved by `lookup_external`).
+pub fn evaluate_constants<'a>(
+    interface: &ast::Interface,
+    content: &'a str,
+    lookup_external: &dyn Fn(&Token) -> Option<i64>,
+) -> HashMap<&'a str, i64> {
+    let declarations = get_body_declarations(
+        content,
+        &interface.symbol_range,
+        &interface.full_range,
+        false,
+    );
+
+    // Evaluate the constants whose references are known until nothing changes (the
+    // constants referencing each other stay unknown)
+    let mut values = HashMap::new();
+    loop {
+        let mut changed = false;
+        for declaration in &declarations {
+            let tokens = match &declaration.value {
+                Some(tokens) if !values.contains_key(declaration.name.text) => tokens,
+                _ => continue,
+            };
+
+            let value = evaluate(
+                tokens,
+                &|t: &Token| match get_referenced_name(t, &interface.name) {
+                    Some(name) => values.get(name.text).copied(),
+                    None => lookup_external(t),
+                },
+            );
+
+            if let Some(value) = value {
+                let type_name = declaration.type_name.map(|t| t.text).unwrap_or_default();
+                values.insert(declaration.name.text, wrap_to_type(value, type_name));
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return values;
+        }
+    }
+}
+
+/// Wrap an integer to the range of a primitive type, as the values are given in two's
+/// complement (e.g. `0xFFFFFFFF` is -1 as an `int`)
+pub fn wrap_to_type(value: i64, type_name: &str) -> i64 {
+    match type_name {
+        "byte" => value as i8 as i64,
+        "short" => value as i16 as i64,
+        "int" => value as i32 as i64,
+        "char" => value as u16 as i64,
+        _ => value,
+    }
+}
+
+// Binary operators, by increasing precedence
+const BINARY_OPERATORS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Evaluator<'a, 'b> {
+    tokens: &'b [Token<'a>],
+    position: usize,
+    lookup: &'b dyn Fn(&Token) -> Option<i64>,
+}
+
+impl Evaluator<'_, '_> {
+    fn evaluate_binary(&mut self, level: usize) -> Option<i64> {
+        let operators = match BINARY_OPERATORS.get(level) {
+            Some(operators) => operators,
+            None => return self.evaluate_unary(),
+        };
+
+        let mut value = self.evaluate_binary(level + 1)?;
+        while let Some(operator) = self
+            .tokens
+            .get(self.position)
+            .map(|t| t.text)
+            .filter(|t| operators.contains(t))
+        {
+            self.position += 1;
+            let rhs = self.evaluate_binary(level + 1)?;
+            value = match operator {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.checked_shl(u32::try_from(rhs).ok()?)?,
+                ">>" => value.checked_shr(u32::try_from(rhs).ok()?)?,
+                "+" => value.checked_add(rhs)?,
+                "-" => value.checked_sub(rhs)?,
+                "*" => value.checked_mul(rhs)?,
+                "/" => value.checked_div(rhs)?,
+                "%" => value.checked_rem(rhs)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    fn evaluate_unary(&mut self) -> Option<i64> {
+        let token = *self.tokens.get(self.position)?;
+        self.position += 1;
+
+        match token.text {
+            "-" => self.evaluate_unary()?.checked_neg(),
+            "+" => self.evaluate_unary(),
+            "~" => Some(!self.evaluate_unary()?),
+            "(" => {
+                let value = self.evaluate_binary(0)?;
+                let closing = self.tokens.get(self.position)?;
+                self.position += 1;
+                (closing.text == ")").then_some(value)
+            }
+            _ if token.is_identifier() => (self.lookup)(&token),
+            text => utils::parse_int_literal(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts<'a>(tokens: &[Token<'a>]) -> Vec<&'a str> {
+        tokens.iter().map(|t| t.text).collect()
+    }
+
+    fn parse(content: &str) -> ast::Aidl {
+        let mut parser = aidl_parser::Parser::new();
+        parser.add_content(0, content);
+        parser.validate().remove(&0).unwrap().ast.unwrap()
+    }
+
+    #[test]
+    fn tokenize_value() {
+        let tokens = tokenize("(IFoo.A << 2) | 0x1F /* comment */ + \"a b\" // end", 10);
+        assert_eq!(
+            texts(&tokens),
+            ["(", "IFoo.A", "<<", "2", ")", "|", "0x1F", "+", "\"a b\""]
+        );
+        assert_eq!(tokens[1].offset, 11);
+        assert_eq!(tokens[8].offset, 47);
+    }
+
+    #[test]
+    fn declarations_of_interface() {
+        let content = "package a;\ninterface IFoo {\n    const int A = 1;\n    @Annotation(key=1) const int B = 1 << A;\n    void f(in int a) = 3;\n}\n";
+        let ast = parse(content);
+        let declarations = get_declarations(&ast.item, content);
+
+        let names: Vec<&str> = declarations.iter().map(|d| d.name.text).collect();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(
+            texts(declarations[1].value.as_ref().unwrap()),
+            ["1", "<<", "A"]
+        );
+        assert_eq!(&content[declarations[1].name.offset..][..1], "B");
+    }
+
+    #[test]
+    fn declarations_of_enum() {
+        let content = "package a;\nenum E {\n    /** doc, with comma */\n    A = 1 << 2,\n    B,\n    C = A,\n}\n";
+        let ast = parse(content);
+        let declarations = get_declarations(&ast.item, content);
+
+        let names: Vec<&str> = declarations.iter().map(|d| d.name.text).collect();
+        assert_eq!(names, ["A", "B", "C"]);
+        assert_eq!(
+            texts(declarations[0].value.as_ref().unwrap()),
+            ["1", "<<", "2"]
+        );
+        assert!(declarations[1].value.is_none());
+    }
+
+    #[test]
+    fn referenced_name() {
+        let token = |text| Token { text, offset: 0 };
+        assert_eq!(
+            get_referenced_name(&token("A"), "E").map(|t| t.text),
+            Some("A")
+        );
+        let qualified = get_referenced_name(&token("a.E.A"), "E").unwrap();
+        assert_eq!((qualified.text, qualified.offset), ("A", 4));
+        assert_eq!(get_referenced_name(&token("F.A"), "E"), None);
+        assert_eq!(get_referenced_name(&token("1"), "E"), None);
+    }
+
+    #[test]
+    fn evaluate_expressions() {
+        let lookup = |t: &Token| match t.text {
+            "A" | "E.A" => Some(4),
+            _ => None,
+        };
+        let eval = |source: &str| evaluate(&tokenize(source, 0), &lookup);
+
+        assert_eq!(eval("1 << 2"), Some(4));
+        assert_eq!(eval("A + 1"), Some(5));
+        assert_eq!(eval("E.A * (2 + 1)"), Some(12));
+        assert_eq!(eval("1 | 2 << 2 & ~0"), Some(9));
+        assert_eq!(eval("-0x10 % 3"), Some(-1));
+        assert_eq!(eval("10L / 4"), Some(2));
+        assert_eq!(eval("B"), None);
+        assert_eq!(eval("1 +"), None);
+        assert_eq!(eval("(1"), None);
+        assert_eq!(eval("1 2"), None);
+        assert_eq!(eval("1 / 0"), None);
+        assert_eq!(eval("\"a\""), None);
+    }
+
+    #[test]
+    fn evaluate_constants_of_interface() {
+        let content = "package a;\ninterface IFoo {\n    const int C = B - IBar.X;\n    const int A = 1;\n    const int B = A << 4;\n    const byte D = B << 3;\n    const int E = F;\n    const int F = E;\n    const String S = \"s\";\n    const int G = IFoo.A - a.IFoo.B;\n    const int H = IBaz.X;\n}\n";
+        let ast = parse(content);
+        let interface = ast.item.as_interface().unwrap();
+        let lookup_external = |t: &Token| match t.text {
+            "IBar.X" => Some(100),
+            _ => None,
+        };
+
+        let values = evaluate_constants(interface, content, &lookup_external);
+        assert_eq!(values.get("A"), Some(&1));
+        assert_eq!(values.get("B"), Some(&16));
+        assert_eq!(values.get("C"), Some(&-84));
+        assert_eq!(values.get("D"), Some(&-128));
+        assert_eq!(values.get("G"), Some(&-15));
+
+        // Cycle, not an integer or unknown reference
+        assert_eq!(values.get("E"), None);
+        assert_eq!(values.get("F"), None);
+        assert_eq!(values.get("S"), None);
+        assert_eq!(values.get("H"), None);
+    }
+
+    #[test]
+    fn wrap_values() {
+        assert_eq!(wrap_to_type(0xFFFFFFFF, "int"), -1);
+        assert_eq!(wrap_to_type(0xFF, "byte"), -1);
+        assert_eq!(wrap_to_type(0x7F, "byte"), 127);
+        assert_eq!(wrap_to_type(1 << 31, "int"), i32::MIN as i64);
+        assert_eq!(wrap_to_type(0xFFFFFFFF, "long"), 0xFFFFFFFF);
+    }
+}
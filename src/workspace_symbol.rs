@@ -0,0 +1,316 @@
+use aidl_parser::ast;
+use aidl_parser::symbol::Symbol;
+use aidl_parser::traverse::{self, SymbolFilter};
+
+use crate::state::GlobalState;
+use crate::utils;
+
+/// Maximum number of symbols returned for a query
+const MAX_RESULTS: usize = 256;
+
+/// How a name matches the query (the lower, the better)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Exact,
+    Prefix,
+    Substring,
+    CamelHump,
+    Fuzzy,
+}
+
+/// Search the symbols of the workspace matching the query, ranked by relevance
+///
+/// The query is matched against the name of the symbols (case-insensitive, fuzzy or by
+/// camel humps, e.g. `IFCb` for `IFooCallback`) and may be qualified with a package or
+/// an item (e.g. `android.hardware.Foo` or `IFoo.bar`). Members (methods, fields, ...)
+/// are only included when the query contains `#`.
+pub fn search(global_state: &GlobalState, query: &str) -> Vec<lsp_types::SymbolInformation> {
+    let symbol_filter = if query.contains('#') {
+        SymbolFilter::All
+    } else {
+        SymbolFilter::ItemsOnly
+    };
+
+    let query: String = query.chars().filter(|&c| c != '#' && c != '*').collect();
+    let (qualifier, name_query) = match query.rsplit_once('.') {
+        Some((qualifier, name)) => (Some(qualifier.to_lowercase()), name),
+        None => (None, query.as_str()),
+    };
+
+    let mut matches = Vec::new();
+    for (uri, ast) in global_state
+        .file_results
+        .iter()
+        .filter_map(|(uri, fr)| fr.ast.as_ref().map(|ast| (uri, ast)))
+    {
+        traverse::walk_symbols(ast, symbol_filter, |symbol| {
+            let name = match symbol.get_name() {
+                Some(n) => n,
+                None => return,
+            };
+
+            let container_name = get_container_name(ast, &symbol);
+            if let Some(qualifier) = &qualifier {
+                let container = format!(".{}", container_name.as_deref().unwrap_or_default());
+                if !container
+                    .to_lowercase()
+                    .ends_with(&format!(".{}", qualifier))
+                {
+                    return;
+                }
+            }
+
+            let match_kind = match match_name(&name, name_query) {
+                Some(k) => k,
+                None => return,
+            };
+
+            if let Some(mut symbol_info) = utils::to_lsp_symbol_info(&symbol, uri.clone()) {
+                symbol_info.container_name = container_name;
+                matches.push((match_kind, symbol_info));
+            }
+        });
+    }
+
+    matches.sort_by(|(kind_a, a), (kind_b, b)| {
+        kind_a
+            .cmp(kind_b)
+            .then_with(|| a.name.len().cmp(&b.name.len()))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            .then_with(|| a.container_name.cmp(&b.container_name))
+    });
+
+    matches
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, symbol_info)| symbol_info)
+        .collect()
+}
+
+// Package (for items) or parent (for members)
+fn get_container_name(ast: &ast::Aidl, symbol: &Symbol) -> Option<String> {
+    match symbol {
+        Symbol::Interface(..) | Symbol::Parcelable(..) | Symbol::Enum(..) => {
+            Some(ast.package.name.clone())
+        }
+        Symbol::Method(..) | Symbol::Const(..) | Symbol::Field(..) | Symbol::EnumElement(..) => {
+            Some(ast.get_key())
+        }
+        Symbol::Arg(_, m) => Some(format!("{}.{}", ast.get_key(), m.name)),
+        _ => None,
+    }
+}
+
+fn match_name(name: &str, query: &str) -> Option<MatchKind> {
+    let lower_name = name.to_lowercase();
+    let lower_query = query.to_lowercase();
+
+    if lower_name == lower_query {
+        Some(MatchKind::Exact)
+    } else if lower_name.starts_with(&lower_query) {
+        Some(MatchKind::Prefix)
+    } else if lower_name.contains(&lower_query) {
+        Some(MatchKind::Substring)
+    } else if matches_camel_humps(name, query) {
+        Some(MatchKind::CamelHump)
+    } else if is_subsequence(&lower_name, &lower_query) {
+        Some(MatchKind::Fuzzy)
+    } else {
+        None
+    }
+}
+
+// Each part of the query (starting with an uppercase letter) starts one of the humps
+// of the name, in order, and its other letters follow in this hump (e.g. `IFCb` for
+// `I|Foo|Callback`)
+fn matches_camel_humps(name: &str, query: &str) -> bool {
+    let humps = split_humps(name);
+    let mut humps = humps.iter().map(|hump| hump.to_lowercase());
+
+    split_query_parts(query).iter().all(|part| {
+        let part = part.to_lowercase();
+        humps.any(|hump| hump.chars().next() == part.chars().next() && is_subsequence(&hump, &part))
+    })
+}
+
+// Humps of a name, an uppercase sequence being a single hump (e.g. `I|Foo|Callback`,
+// `HTTP|Server` or `MY|VALUE`)
+fn split_humps(name: &str) -> Vec<&str> {
+    split_words(name, |prev, c, next| {
+        c.is_uppercase()
+            && (!prev.is_uppercase() || next.map(|n| n.is_lowercase()).unwrap_or(false))
+    })
+}
+
+// Parts of a query, each uppercase letter starting a new part (e.g. `I|F|Cb`)
+fn split_query_parts(query: &str) -> Vec<&str> {
+    split_words(query, |_, c, _| c.is_uppercase())
+}
+
+// Split at the underscores and before the characters for which `starts_word(previous,
+// current, next)` is true
+fn split_words(s: &str, starts_word: impl Fn(char, char, Option<char>) -> bool) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut prev = '_';
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, n)| *n);
+        if c == '_' {
+            if i > start {
+                words.push(&s[start..i]);
+            }
+            start = i + 1;
+        } else if i > start && starts_word(prev, c, next) {
+            words.push(&s[start..i]);
+            start = i;
+        }
+        prev = c;
+    }
+
+    if start < s.len() {
+        words.push(&s[start..]);
+    }
+
+    words
+}
+
+fn is_subsequence(s: &str, sub: &str) -> bool {
+    let mut chars = s.chars();
+    sub.chars().all(|c| chars.any(|other| other == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing;
+
+    fn names(symbols: &[lsp_types::SymbolInformation]) -> Vec<&str> {
+        symbols.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn match_kinds() {
+        assert_eq!(match_name("IFoo", "IFoo"), Some(MatchKind::Exact));
+        assert_eq!(match_name("IFooCallback", "IFoo"), Some(MatchKind::Prefix));
+        assert_eq!(match_name("IBarFoo", "Foo"), Some(MatchKind::Substring));
+        assert_eq!(
+            match_name("IFooCallback", "IFCb"),
+            Some(MatchKind::CamelHump)
+        );
+        assert_eq!(
+            match_name("MY_FOO_VALUE", "MyFV"),
+            Some(MatchKind::CamelHump)
+        );
+        assert_eq!(match_name("IFooCallback", "focal"), Some(MatchKind::Fuzzy));
+        assert_eq!(match_name("IFooCallback", "IBar"), None);
+        assert_eq!(match_name("IFoo", "IFooo"), None);
+    }
+
+    #[test]
+    fn match_case() {
+        // Case-insensitive, except for the camel humps which are given by the uppercase
+        // letters of the query
+        assert_eq!(match_name("IFoo", "ifoo"), Some(MatchKind::Exact));
+        assert_eq!(match_name("IFooCallback", "ifooc"), Some(MatchKind::Prefix));
+        assert_eq!(
+            match_name("IFooCallback", "IFCb"),
+            Some(MatchKind::CamelHump)
+        );
+        assert_eq!(match_name("IFooCallback", "ifcb"), Some(MatchKind::Fuzzy));
+        assert_eq!(match_name("IFooCallback", "IFCB"), Some(MatchKind::Fuzzy));
+    }
+
+    #[test]
+    fn humps() {
+        assert_eq!(split_humps("IFooCallback"), ["I", "Foo", "Callback"]);
+        assert_eq!(split_humps("MY_FOO_VALUE"), ["MY", "FOO", "VALUE"]);
+        assert_eq!(split_humps("HTTPServer"), ["HTTP", "Server"]);
+        assert_eq!(split_humps("_fooBar_"), ["foo", "Bar"]);
+        assert_eq!(split_query_parts("IFCb"), ["I", "F", "Cb"]);
+        assert_eq!(split_query_parts("MY_FV"), ["M", "Y", "F", "V"]);
+    }
+
+    #[test]
+    fn ranking() {
+        let (global_state, _receiver) = indexing::index_test_files(&[
+            ("a/IFoo.aidl", "package a;\ninterface IFoo {}\n"),
+            (
+                "a/IFooCallback.aidl",
+                "package a;\ninterface IFooCallback {}\n",
+            ),
+            ("a/IFox.aidl", "package a;\ninterface IFox {}\n"),
+            ("a/XIFoo.aidl", "package a;\ninterface XIFoo {}\n"),
+            ("b/IFoo.aidl", "package b;\ninterface IFoo {}\n"),
+            ("b/IBarFoo.aidl", "package b;\ninterface IBarFoo {}\n"),
+            (
+                "b/IFirstOrder.aidl",
+                "package b;\ninterface IFirstOrder {}\n",
+            ),
+            (
+                "b/IFlightOfOwls.aidl",
+                "package b;\ninterface IFlightOfOwls {}\n",
+            ),
+        ]);
+
+        // Exact, prefix, substring, camel humps then fuzzy, the shortest names first
+        let symbols = search(&global_state, "IFoo");
+        assert_eq!(
+            names(&symbols),
+            [
+                "IFoo",
+                "IFoo",
+                "IFooCallback",
+                "XIFoo",
+                "IBarFoo",
+                "IFlightOfOwls"
+            ]
+        );
+        assert_eq!(symbols[0].container_name.as_deref(), Some("a"));
+        assert_eq!(symbols[1].container_name.as_deref(), Some("b"));
+
+        assert_eq!(
+            names(&search(&global_state, "Foo")),
+            [
+                "IFoo",
+                "IFoo",
+                "XIFoo",
+                "IBarFoo",
+                "IFooCallback",
+                "IFlightOfOwls"
+            ]
+        );
+        assert_eq!(
+            names(&search(&global_state, "b.IFoo")),
+            ["IFoo", "IBarFoo", "IFlightOfOwls"]
+        );
+    }
+
+    #[test]
+    fn members() {
+        let (global_state, _receiver) = indexing::index_test_files(&[(
+            "a/IFoo.aidl",
+            "package a;\ninterface IFoo {\n    void foo();\n    const int FOO = 1;\n}\n",
+        )]);
+
+        assert_eq!(names(&search(&global_state, "foo")), ["IFoo"]);
+        assert_eq!(
+            names(&search(&global_state, "foo#")),
+            ["foo", "FOO", "IFoo"]
+        );
+        assert_eq!(names(&search(&global_state, "IFoo.foo#")), ["foo", "FOO"]);
+    }
+
+    #[test]
+    fn max_results() {
+        let methods: String = (0..300).map(|i| format!("    void f{}();\n", i)).collect();
+        let content = format!("package a;\ninterface IFoo {{\n{}}}\n", methods);
+        let (global_state, _receiver) = indexing::index_test_files(&[("a/IFoo.aidl", &content)]);
+
+        let symbols = search(&global_state, "f#");
+        assert_eq!(symbols.len(), MAX_RESULTS);
+        assert_eq!(names(&symbols[..3]), ["f0", "f1", "f2"]);
+        assert_eq!(symbols.last().unwrap().name, "f255");
+    }
+}